@@ -0,0 +1,6 @@
+use std::time::Duration;
+
+/// Whatever gets drawn with imgui each frame. `delta` is the time since the last frame.
+pub trait App {
+    fn ui(&mut self, ui: &imgui::Ui, delta: Duration);
+}
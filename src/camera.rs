@@ -0,0 +1,113 @@
+use glam::{Mat4, Vec3};
+
+// keeps the camera from flipping over the poles
+const MAX_PITCH: f32 = 1.5;
+const MIN_DISTANCE: f32 = 0.1;
+
+// radians per pixel of drag
+const ORBIT_SPEED: f32 = 0.005;
+// fraction of the distance per scroll line
+const ZOOM_SPEED: f32 = 0.1;
+
+/// Orbits `target` at `distance`, looking at it from `yaw`/`pitch` (radians).
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub target: Vec3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Vertical field of view, in radians.
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            target: Vec3::ZERO,
+            distance: 3.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            fov_y: 45f32.to_radians(),
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn position(&self) -> Vec3 {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        self.target + self.distance * Vec3::new(cos_pitch * sin_yaw, sin_pitch, cos_pitch * cos_yaw)
+    }
+
+    /// Projection times view, for wgpu's clip space (depth in 0..1).
+    pub fn view_proj(&self, aspect: f32) -> Mat4 {
+        let view = Mat4::look_at_rh(self.position(), self.target, Vec3::Y);
+        let proj = Mat4::perspective_rh(self.fov_y, aspect, self.near, self.far);
+        proj * view
+    }
+
+    /// Turns the camera around the target by a mouse drag of `dx`x`dy` pixels.
+    pub fn orbit(&mut self, dx: f32, dy: f32) {
+        self.yaw -= dx * ORBIT_SPEED;
+        self.pitch = (self.pitch + dy * ORBIT_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Moves towards the target for positive `lines` of scrolling, away for negative.
+    pub fn zoom(&mut self, lines: f32) {
+        self.distance = (self.distance * (1.0 - lines * ZOOM_SPEED)).max(MIN_DISTANCE);
+    }
+}
+
+// the camera's matrix on the gpu, as a uniform at binding 0 for any pipeline that wants it
+pub(crate) struct CameraUniform {
+    buffer: wgpu::Buffer,
+    pub(crate) bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) bind_group: wgpu::BindGroup,
+}
+
+impl CameraUniform {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("camera uniform buffer"),
+            size: std::mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("camera bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("camera bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(buffer.slice(..)),
+                },
+            ],
+        });
+
+        CameraUniform { buffer, bind_group_layout, bind_group }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, view_proj: Mat4) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&view_proj.to_cols_array()));
+    }
+}
@@ -0,0 +1,347 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+use futures::executor::block_on;
+use log::info;
+use winit::dpi::PhysicalSize;
+
+use crate::app::App;
+use crate::gui::ImguiState;
+use crate::renderer::{Output, Renderer};
+use crate::texture::Texture;
+
+// texture -> buffer copies need each row padded out to a multiple of COPY_BYTES_PER_ROW_ALIGNMENT
+fn padded_bytes_per_row(width: u32, bytes_per_pixel: u32) -> u32 {
+    let unpadded = width * bytes_per_pixel;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded.div_ceil(align) * align
+}
+
+// how many unmapped readback buffers to hang on to, so a capture sequence doesn't allocate one a frame
+const MAX_POOLED_READBACK_BUFFERS: usize = 4;
+
+// readback buffers that are done with, by size. a RefCell since reading back only needs &Renderer
+#[derive(Default)]
+pub(crate) struct ReadbackPool {
+    buffers: RefCell<Vec<(wgpu::BufferAddress, wgpu::Buffer)>>,
+}
+
+impl ReadbackPool {
+    fn take(&self, device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+        let mut buffers = self.buffers.borrow_mut();
+        match buffers.iter().position(|(pooled, _)| *pooled == size) {
+            Some(index) => buffers.remove(index).1,
+            None => device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("readback buffer"),
+                size,
+                usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+
+    // the oldest go first, they're likely from before a resize
+    fn put(&self, size: wgpu::BufferAddress, buffer: wgpu::Buffer) {
+        let mut buffers = self.buffers.borrow_mut();
+        if buffers.len() == MAX_POOLED_READBACK_BUFFERS {
+            buffers.remove(0);
+        }
+        buffers.push((size, buffer));
+    }
+}
+
+// copies a whole 2d texture back to the cpu, returning tightly packed rows
+pub(crate) fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pool: &ReadbackPool,
+    texture: &wgpu::Texture,
+    width: u32,
+    height: u32,
+    bytes_per_pixel: u32,
+) -> Result<Vec<u8>> {
+    read_texture_region(device, queue, pool, texture, wgpu::Origin3d::ZERO, width, height, bytes_per_pixel)
+}
+
+// `read_texture` for just the `width` by `height` pixels starting at `origin`
+#[allow(clippy::too_many_arguments)]
+fn read_texture_region(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pool: &ReadbackPool,
+    texture: &wgpu::Texture,
+    origin: wgpu::Origin3d,
+    width: u32,
+    height: u32,
+    bytes_per_pixel: u32,
+) -> Result<Vec<u8>> {
+    let padded_row = padded_bytes_per_row(width, bytes_per_pixel);
+    let size = (padded_row * height) as wgpu::BufferAddress;
+    let buffer = pool.take(device, size);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("readback encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::TextureCopyView {
+            texture,
+            mip_level: 0,
+            origin,
+        },
+        wgpu::BufferCopyView {
+            buffer: &buffer,
+            layout: wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: padded_row,
+                rows_per_image: height,
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    // the map callback only fires once the device gets polled, and this has to block until it does
+    device.poll(wgpu::Maintain::Wait);
+    block_on(mapping).context("couldn't map the readback buffer")?;
+
+    let row = (width * bytes_per_pixel) as usize;
+    let mut pixels = Vec::with_capacity(row * height as usize);
+    for padded in slice.get_mapped_range().chunks(padded_row as usize) {
+        pixels.extend_from_slice(&padded[..row]);
+    }
+    buffer.unmap();
+    pool.put(size, buffer);
+
+    Ok(pixels)
+}
+
+// turns readback bytes in `format` into rgba8, or None if we don't know how
+fn to_rgba8(format: wgpu::TextureFormat, mut pixels: Vec<u8>) -> Option<Vec<u8>> {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => Some(pixels),
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+            Some(pixels)
+        },
+        _ => None,
+    }
+}
+
+// the numbered frames start_capture_sequence still has to write
+pub(crate) struct CaptureSequence {
+    dir: PathBuf,
+    next: u32,
+    count: u32,
+}
+
+impl Renderer {
+    /// Saves the next rendered frame, ui included, to `path` as a png. With msaa on it's the
+    /// resolved frame, exactly what gets presented. A frame that fails to render doesn't count, the
+    /// capture waits for the next one that does.
+    pub fn capture_frame(&mut self, path: &Path) -> Result<()> {
+        if let Some(pending) = &self.pending_capture {
+            return Err(anyhow!("already capturing a frame to {}", pending.display()));
+        }
+
+        self.pending_capture = Some(path.to_owned());
+        Ok(())
+    }
+
+    /// Renders one frame of a headless renderer and returns it as tightly packed rgba8, `width * height * 4` bytes.
+    pub fn render_to_texture(&mut self, imstate: Option<&mut ImguiState>, app: &mut dyn App) -> Result<Vec<u8>> {
+        if let Output::Window { .. } = self.output {
+            return Err(anyhow!("render_to_texture needs a renderer from Renderer::new_headless"));
+        }
+
+        self.render_headless(imstate, app)?;
+        match &self.output {
+            Output::Texture { texture, .. } => self.read_frame(texture),
+            Output::Window { .. } => unreachable!(),
+        }
+    }
+
+    /// Saves the next `count` frames to `dir` (which gets created) as `frame-00000.png` onwards,
+    /// like `capture_frame` does one. Every frame blocks on its readback, so expect it to run slower.
+    pub fn start_capture_sequence(&mut self, dir: &Path, count: u32) -> Result<()> {
+        if let Some(sequence) = &self.capture_sequence {
+            return Err(anyhow!("already capturing frames into {}", sequence.dir.display()));
+        }
+        if count == 0 {
+            return Err(anyhow!("a capture sequence needs at least one frame"));
+        }
+
+        std::fs::create_dir_all(dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+        info!("capturing {} frames into {}", count, dir.display());
+        self.capture_sequence = Some(CaptureSequence { dir: dir.to_owned(), next: 0, count });
+        Ok(())
+    }
+
+    /// How many frames the current capture sequence has written, and how many it's writing in total.
+    pub fn capture_sequence_progress(&self) -> Option<(u32, u32)> {
+        self.capture_sequence.as_ref().map(|sequence| (sequence.next, sequence.count))
+    }
+
+    pub fn cancel_capture_sequence(&mut self) {
+        if let Some(sequence) = self.capture_sequence.take() {
+            info!("stopped capturing into {} after {} frames", sequence.dir.display(), sequence.next);
+        }
+    }
+
+    // where this frame should be saved, if anywhere: a single capture comes before the sequence's
+    // next. nothing's used up until commit_capture, so a frame that fails leaves it for the next one
+    pub(crate) fn capture_path(&self) -> Option<PathBuf> {
+        if let Some(path) = &self.pending_capture {
+            return Some(path.clone());
+        }
+
+        let sequence = self.capture_sequence.as_ref()?;
+        Some(sequence.dir.join(format!("frame-{:05}.png", sequence.next)))
+    }
+
+    // called once a frame has been saved to `path`, to use up the capture it was for and stop after
+    // the sequence's last
+    pub(crate) fn commit_capture(&mut self, path: &Path) {
+        if self.pending_capture.as_deref() == Some(path) {
+            self.pending_capture = None;
+            return;
+        }
+
+        if let Some(sequence) = &mut self.capture_sequence {
+            sequence.next += 1;
+        }
+        if let Some(sequence) = self.capture_sequence.take_if(|sequence| sequence.next == sequence.count) {
+            info!("captured {} frames into {}", sequence.count, sequence.dir.display());
+        }
+    }
+
+    // reads back a texture shaped like the output as rgba8
+    fn read_frame(&self, texture: &Texture) -> Result<Vec<u8>> {
+        // multisampled textures can't be copied from, only resolved into something that can
+        if texture.sample_count > 1 {
+            return Err(anyhow!("can't read back a {}x multisampled frame, it has to be resolved first", texture.sample_count));
+        }
+
+        let format = self.swapchain_desc.format;
+        let pixels = read_texture(&self.device, &self.queue, &self.readback_pool, &texture.texture, self.size.width, self.size.height, 4)?;
+        to_rgba8(format, pixels).ok_or_else(|| anyhow!("don't know how to read back a {:?} frame", format))
+    }
+
+    /// Keeps a copy of every frame a window draws (at the cost of one more blit per frame), so
+    /// `read_pixel` has something to read: swapchain frames can't be read back. Headless renderers
+    /// can always be read from.
+    pub fn set_keep_last_frame(&mut self, keep: bool) {
+        self.keep_last_frame = keep;
+        if !keep {
+            self.last_frame = None;
+        }
+    }
+
+    pub fn keep_last_frame(&self) -> bool {
+        self.keep_last_frame
+    }
+
+    /// The rgba8 value of the pixel at `x`, `y` (in physical pixels from the top left) of the last
+    /// frame, ui included. Windows need `set_keep_last_frame` on first.
+    pub fn read_pixel(&mut self, x: u32, y: u32) -> Result<[u8; 4]> {
+        let texture = match (&self.output, &self.last_frame) {
+            (Output::Texture { texture }, _) | (Output::Window { .. }, Some(texture)) => texture,
+            (Output::Window { .. }, None) => return Err(anyhow!("no frame to read a pixel from, turn on set_keep_last_frame and draw one")),
+        };
+        if x >= texture.size.width || y >= texture.size.height {
+            return Err(anyhow!("pixel ({}, {}) is outside the {}x{} frame", x, y, texture.size.width, texture.size.height));
+        }
+
+        let format = self.swapchain_desc.format;
+        let pixel = read_texture_region(&self.device, &self.queue, &self.readback_pool, &texture.texture, wgpu::Origin3d { x, y, z: 0 }, 1, 1, 4)?;
+        let pixel = to_rgba8(format, pixel).ok_or_else(|| anyhow!("don't know how to read back a {:?} frame", format))?;
+        Ok([pixel[0], pixel[1], pixel[2], pixel[3]])
+    }
+
+    /// The depth buffer from the last frame, as distances from the camera between its near and far
+    /// planes (exactly far where nothing was drawn). Rows of `depth_size().width`, top to bottom.
+    /// Multisampled depth can't be copied, so this fails with msaa on.
+    pub fn read_depth(&mut self) -> Result<Vec<f32>> {
+        let far = self.camera().far;
+        Ok(self.read_raw_depth()?.into_iter()
+            .map(|depth| self.depth_to_distance(depth).unwrap_or(far))
+            .collect())
+    }
+
+    // the depth buffer as it's stored, 0 at the near plane and 1 at the far one
+    fn read_raw_depth(&mut self) -> Result<Vec<f32>> {
+        if self.sample_count() > 1 {
+            return Err(anyhow!("can't read back depth with {}x msaa on", self.sample_count()));
+        }
+
+        // read_texture pads each row of 4 byte depth values out to the copy alignment and strips it again
+        let size = self.depth_size();
+        let bytes = read_texture(&self.device, &self.queue, &self.readback_pool, &self.depth.texture, size.width, size.height, 4)?;
+
+        Ok(bytes.chunks_exact(4)
+            .map(|depth| f32::from_le_bytes([depth[0], depth[1], depth[2], depth[3]]))
+            .collect())
+    }
+
+    // undoes the perspective divide, or None for the cleared background: converting 1.0 doesn't
+    // land exactly on the far plane in f32, so it has to be caught before
+    fn depth_to_distance(&self, depth: f32) -> Option<f32> {
+        if depth >= 1.0 {
+            return None;
+        }
+        let (near, far) = (self.camera().near, self.camera().far);
+        Some(near * far / (far - depth * (far - near)))
+    }
+
+    /// `read_depth` as a grayscale image for looking at, black at the nearest thing drawn and white
+    /// at the farthest, so there's contrast whatever the camera's planes are. Where nothing was
+    /// drawn is white too.
+    pub fn depth_image(&mut self) -> Result<image::RgbaImage> {
+        // the cleared background sits at the far plane, which would squash everything else into black
+        let distances: Vec<Option<f32>> = self.read_raw_depth()?.into_iter()
+            .map(|depth| self.depth_to_distance(depth))
+            .collect();
+        let drawn = || distances.iter().flatten().copied();
+        let nearest = drawn().fold(f32::INFINITY, f32::min);
+        let farthest = drawn().fold(0.0, f32::max);
+        let range = (farthest - nearest).max(f32::EPSILON);
+
+        let size = self.depth_size();
+        let pixels = distances.iter()
+            .flat_map(|&distance| {
+                let gray = match distance {
+                    Some(distance) => ((distance - nearest) / range * 255.0).round() as u8,
+                    None => 255,
+                };
+                [gray, gray, gray, 255]
+            })
+            .collect();
+        image::RgbaImage::from_raw(size.width, size.height, pixels)
+            .ok_or_else(|| anyhow!("depth readback returned the wrong number of pixels"))
+    }
+
+    /// What `read_depth` returns the size of: the scene's, so smaller than the output with a render scale below 1.
+    pub fn depth_size(&self) -> PhysicalSize<u32> {
+        self.scene_size()
+    }
+
+    pub(crate) fn save_capture(&self, texture: &Texture, path: &Path) -> Result<()> {
+        let (width, height) = (self.size.width, self.size.height);
+        let pixels = self.read_frame(texture)?;
+
+        image::RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| anyhow!("readback returned the wrong number of pixels"))?
+            .save_with_format(path, image::ImageFormat::Png)
+            .with_context(|| format!("couldn't write {}", path.display()))?;
+
+        info!("saved frame to {}", path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::gui::Theme;
+use crate::keys::KeyBindings;
+
+/// Settings read from `config.toml` at startup. Anything missing from the file gets its default.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub title: String,
+    /// Initial window size, in physical pixels.
+    pub width: u32,
+    pub height: u32,
+    pub backend: Backend,
+    /// How long to wait for the adapter and device before giving up, in seconds.
+    pub init_timeout_secs: u64,
+    /// `None` lets the renderer pick.
+    pub output_format: Option<OutputFormat>,
+    pub vsync: bool,
+    /// Fraction of the window resolution to draw the scene at.
+    pub render_scale: f32,
+    /// Used instead of the scale factor the platform reports, for displays that get it wrong.
+    pub scale_factor: Option<f64>,
+    /// Msaa samples per pixel, 1 for none.
+    pub sample_count: u32,
+    pub frame_cap: Option<u32>,
+    /// sRGB, like the color picker. Overrides whatever background color was saved last run.
+    pub clear_color: Option<[f32; 3]>,
+    /// Overrides whatever theme was saved last run.
+    pub theme: Option<Theme>,
+    /// A ttf/otf font to use instead of imgui's built in one.
+    pub font: Option<PathBuf>,
+    /// Size of `font`, in logical pixels.
+    pub font_size: f32,
+    /// More fonts to load at `font_size`, by name, for switching between in the ui.
+    pub fonts: BTreeMap<String, PathBuf>,
+    /// Keeps new windows hidden until something's been drawn in them, so they don't flash whatever
+    /// was in the framebuffer first. Some platforms (wayland) show them straight away regardless.
+    pub hide_until_drawn: bool,
+    pub keys: KeyBindings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            title: "pepesilvia".to_owned(),
+            width: 1280,
+            height: 720,
+            backend: Backend::Primary,
+            init_timeout_secs: 10,
+            output_format: None,
+            vsync: true,
+            render_scale: 1.0,
+            scale_factor: None,
+            sample_count: 1,
+            frame_cap: None,
+            clear_color: None,
+            theme: None,
+            font: None,
+            font_size: 13.0,
+            fonts: BTreeMap::new(),
+            hide_until_drawn: true,
+            keys: KeyBindings::default(),
+        }
+    }
+}
+
+/// Which wgpu backends to look for an adapter on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Primary,
+    Secondary,
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl,
+}
+
+impl From<Backend> for wgpu::BackendBit {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Primary => wgpu::BackendBit::PRIMARY,
+            Backend::Secondary => wgpu::BackendBit::SECONDARY,
+            Backend::Vulkan => wgpu::BackendBit::VULKAN,
+            Backend::Metal => wgpu::BackendBit::METAL,
+            Backend::Dx12 => wgpu::BackendBit::DX12,
+            Backend::Dx11 => wgpu::BackendBit::DX11,
+            Backend::Gl => wgpu::BackendBit::GL,
+        }
+    }
+}
+
+/// What the window (or offscreen texture) gets drawn in, for tools that want one channel order
+/// or the other. Not every backend can present all of them, see `Renderer::preferred_formats`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    Bgra8Srgb,
+    Rgba8Srgb,
+    Bgra8,
+    Rgba8,
+}
+
+impl From<OutputFormat> for wgpu::TextureFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Bgra8Srgb => wgpu::TextureFormat::Bgra8UnormSrgb,
+            OutputFormat::Rgba8Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            OutputFormat::Bgra8 => wgpu::TextureFormat::Bgra8Unorm,
+            OutputFormat::Rgba8 => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+/// Where the config lives by default, next to the saved imgui layout.
+pub fn default_config_path() -> Option<PathBuf> {
+    crate::gui::default_layout_path().map(|path| path.with_file_name("config.toml"))
+}
+
+impl Config {
+    /// Reads `path`, or writes the defaults there if it doesn't exist yet so there's something to edit.
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).with_context(|| format!("couldn't parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let config = Config::default();
+                match config.save(path) {
+                    Ok(()) => info!("wrote a default config to {}", path.display()),
+                    Err(e) => warn!("{:?}", e),
+                }
+                Ok(config)
+            },
+            Err(e) => Err(e).with_context(|| format!("couldn't read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = toml::to_string_pretty(self).context("couldn't serialize the config")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+        }
+        std::fs::write(path, text).with_context(|| format!("couldn't write {}", path.display()))
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use anyhow::{Context, Result};
+use imgui::im_str;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+// oldest lines get dropped past this
+const CONSOLE_CAPACITY: usize = 1000;
+
+const LEVELS: [Level; 5] = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+struct Line {
+    level: Level,
+    target: String,
+    message: String,
+}
+
+type Lines = Arc<Mutex<VecDeque<Line>>>;
+
+// forwards to env_logger as usual and keeps a copy of what it lets through for the console
+struct ConsoleLogger {
+    env: env_logger::Logger,
+    lines: Lines,
+}
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.env.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.env.matches(record) {
+            return;
+        }
+        self.env.log(record);
+
+        if let Ok(mut lines) = self.lines.lock() {
+            if lines.len() == CONSOLE_CAPACITY {
+                lines.pop_front();
+            }
+            lines.push_back(Line {
+                level: record.level(),
+                target: record.target().to_owned(),
+                message: record.args().to_string(),
+            });
+        }
+    }
+
+    fn flush(&self) {
+        self.env.flush();
+    }
+}
+
+/// An imgui window showing recent log messages. Clones show the same messages.
+#[derive(Clone)]
+pub struct LogConsole {
+    lines: Lines,
+    // index into LEVELS; shows that level and everything more severe
+    filter: usize,
+    // how many of LEVELS the installed filter lets through, so the combo doesn't offer the rest
+    levels: usize,
+    autoscroll: bool,
+}
+
+impl LogConsole {
+    /// Sets up logging (instead of `env_logger::init`) so messages end up in the console as well as stderr.
+    /// Prints `default_level` and worse unless `RUST_LOG` says otherwise, and the console sees the
+    /// same messages, so `RUST_LOG` is what turns on debug or trace there too.
+    pub fn install(default_level: LevelFilter) -> Result<Self> {
+        let env = env_logger::Env::default().default_filter_or(default_level.as_str());
+        let env = env_logger::Builder::from_env(env).build();
+        let lines = Lines::default();
+
+        let max_level = env.filter();
+        log::set_max_level(max_level);
+        log::set_boxed_logger(Box::new(ConsoleLogger { env, lines: lines.clone() }))
+            .context("a logger was already installed")?;
+
+        // error always stays in the combo, even if logging's off altogether
+        let levels = LEVELS.iter().filter(|&&level| level <= max_level).count().max(1);
+        Ok(LogConsole {
+            lines,
+            filter: LEVELS.iter().position(|&level| level == Level::Info).unwrap_or(0).min(levels - 1),
+            levels,
+            autoscroll: true,
+        })
+    }
+
+    pub fn draw(&mut self, ui: &imgui::Ui, opened: &mut bool) {
+        let (lines, filter, levels, autoscroll) = (&self.lines, &mut self.filter, self.levels, &mut self.autoscroll);
+        imgui::Window::new(im_str!("Log"))
+            .size([600.0, 300.0], imgui::Condition::FirstUseEver)
+            .opened(opened)
+            .build(ui, || {
+                let names = [im_str!("error"), im_str!("warn"), im_str!("info"), im_str!("debug"), im_str!("trace")];
+                let items: Vec<_> = names[..levels].iter().collect();
+                ui.set_next_item_width(100.0);
+                imgui::ComboBox::new(im_str!("Level")).build_simple_string(ui, filter, &items);
+                ui.same_line(0.0);
+                ui.checkbox(im_str!("Autoscroll"), autoscroll);
+                ui.separator();
+
+                let max_level = LEVELS[*filter];
+                imgui::ChildWindow::new("log lines").horizontal_scrollbar(true).build(ui, || {
+                    if let Ok(lines) = lines.lock() {
+                        for line in lines.iter().filter(|line| line.level <= max_level) {
+                            ui.text_colored(level_color(line.level), format!("[{}] {}: {}", line.level, line.target, line.message));
+                        }
+                    }
+
+                    // only follow new lines if we were already at the bottom
+                    if *autoscroll && ui.scroll_y() >= ui.scroll_max_y() {
+                        ui.set_scroll_here_y_with_ratio(1.0);
+                    }
+                });
+            });
+    }
+}
+
+fn level_color(level: Level) -> [f32; 4] {
+    match level {
+        Level::Error => [1.0, 0.4, 0.4, 1.0],
+        Level::Warn => [1.0, 0.8, 0.3, 1.0],
+        Level::Info => [0.9, 0.9, 0.9, 1.0],
+        Level::Debug => [0.6, 0.8, 1.0, 1.0],
+        Level::Trace => [0.6, 0.6, 0.6, 1.0],
+    }
+}
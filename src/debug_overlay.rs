@@ -0,0 +1,69 @@
+use std::time::Duration;
+use imgui::im_str;
+
+use crate::renderer::Renderer;
+
+/// Everything the renderer can report about how it's doing, in one window. Metrics the device
+/// can't provide (gpu timings, allocator stats) are left out rather than shown as blank.
+#[derive(Default)]
+pub struct DebugOverlay {
+    show_memory: bool,
+    show_imgui_metrics: bool,
+    reset_requested: bool,
+}
+
+impl DebugOverlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn draw(&mut self, ui: &imgui::Ui, renderer: &Renderer, opened: &mut bool) {
+        let ms = |d: Duration| d.as_secs_f32() * 1000.0;
+        let (show_memory, show_imgui_metrics, reset) = (&mut self.show_memory, &mut self.show_imgui_metrics, &mut self.reset_requested);
+        imgui::Window::new(im_str!("Debug overlay"))
+            .size([400.0, 260.0], imgui::Condition::FirstUseEver)
+            .opened(opened)
+            .build(ui, || {
+                let stats = renderer.frame_stats();
+                ui.text(im_str!("{:.1} fps ({:.2} ms)", renderer.current_fps(), ms(renderer.frame_time())));
+                let times: Vec<f32> = stats.iter().map(ms).collect();
+                ui.plot_lines(im_str!("##frame times"), &times)
+                    .scale_min(0.0)
+                    .graph_size([0.0, 80.0])
+                    .overlay_text(&im_str!("{} frames", times.len()))
+                    .build();
+                ui.text(im_str!("avg {:.2} ms, min {:.2} ms, max {:.2} ms, p99 {:.2} ms",
+                    ms(stats.avg()), ms(stats.min()), ms(stats.max()), ms(stats.p99())));
+                if ui.button(im_str!("Reset"), [0.0, 0.0]) {
+                    *reset = true;
+                }
+                if let Some(gpu_time) = renderer.last_gpu_time() {
+                    ui.text(im_str!("GPU time: {:.2} ms", ms(gpu_time)));
+                }
+
+                ui.separator();
+                let adapter = renderer.adapter_info();
+                ui.text(im_str!("{} ({:?})", adapter.name, adapter.backend));
+                let size = renderer.size();
+                ui.text(im_str!("{}x{} at {:.2}x scale, {}x msaa", size.width, size.height, renderer.render_scale(), renderer.sample_count()));
+                ui.text(im_str!("present mode: {:?}{}", renderer.present_mode(), if renderer.is_paused() { ", paused" } else { "" }));
+
+                if let Some(report) = renderer.memory_report() {
+                    ui.checkbox(im_str!("GPU memory"), show_memory);
+                    if *show_memory {
+                        ui.text(report);
+                    }
+                }
+                ui.checkbox(im_str!("imgui metrics"), show_imgui_metrics);
+            });
+
+        if self.show_imgui_metrics {
+            ui.show_metrics_window(&mut self.show_imgui_metrics);
+        }
+    }
+
+    /// Whether Reset was clicked since the last call, for the caller to reset the renderer's stats.
+    pub fn take_reset_request(&mut self) -> bool {
+        std::mem::take(&mut self.reset_requested)
+    }
+}
@@ -0,0 +1,414 @@
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+use log::error;
+use imgui::im_str;
+
+use pepesilvia::{App, Action, DebugOverlay, KeyBindings, LogConsole, Renderer, ImguiState, NamedFonts, Theme};
+
+use crate::CAPTURE_SEQUENCE_FRAMES;
+
+pub(crate) struct LoadedImage {
+    id: imgui::TextureId,
+    size: [f32; 2],
+    path: PathBuf,
+}
+
+pub(crate) struct DemoApp {
+    pub(crate) images: Vec<LoadedImage>,
+    show_demo: bool,
+    // drawn in the window's redraw, since it reads straight from the renderer
+    pub(crate) show_debug_overlay: bool,
+    pub(crate) debug_overlay: DebugOverlay,
+    // the renderer's last caught wgpu error, cleared there when this is dismissed
+    pub(crate) gpu_error: Option<String>,
+    pub(crate) dismiss_gpu_error: bool,
+    console: LogConsole,
+    show_console: bool,
+    // the depth buffer as a grayscale texture and its size; the window reads it back after a frame
+    // when asked to, or every frame when live
+    pub(crate) depth_image: Option<(imgui::TextureId, [f32; 2])>,
+    pub(crate) show_depth: bool,
+    pub(crate) refresh_depth: bool,
+    pub(crate) live_depth: bool,
+    // clicking the scene with this open reads back the pixel under the cursor, at that position
+    pub(crate) show_picker: bool,
+    pub(crate) picked_pixel: Option<([u32; 2], [u8; 4])>,
+    // File -> Capture frames asks for a sequence, and how far one's got is shown there
+    pub(crate) start_capture_sequence: bool,
+    pub(crate) capture_progress: Option<(u32, u32)>,
+    pub(crate) paused: bool,
+    // whether the window has a title bar; synced and handed back like the present mode
+    pub(crate) decorations: bool,
+    pub(crate) always_on_top: bool,
+    // synced from the renderer before each frame, and handed back if the ui changed it
+    pub(crate) present_mode: wgpu::PresentMode,
+    pub(crate) supported_present_modes: &'static [wgpu::PresentMode],
+    // same for tonemapping and the particles
+    pub(crate) tonemapping: bool,
+    pub(crate) show_particles: bool,
+    pub(crate) wireframe: bool,
+    pub(crate) exposure: f32,
+    pub(crate) clear_color: [f32; 3],
+    // a file being dragged over the window
+    pub(crate) hovered_file: Option<PathBuf>,
+    // the event loop hands this to ImguiState when it changes
+    pub(crate) theme: Theme,
+    // same for the default font, picked from the names ImguiState has
+    pub(crate) fonts: NamedFonts,
+    font_names: Vec<String>,
+    default_font: String,
+    // set from File -> Quit, File -> New window and File -> Reload config, the event loop polls them
+    pub(crate) quit: Rc<Cell<bool>>,
+    new_window: Rc<Cell<bool>>,
+    pub(crate) reload_config: Rc<Cell<bool>>,
+    // the window looks its shortcuts up here, and the menus show them
+    pub(crate) key_bindings: KeyBindings,
+}
+
+impl DemoApp {
+    pub(crate) fn new(console: LogConsole, quit: Rc<Cell<bool>>, new_window: Rc<Cell<bool>>, reload_config: Rc<Cell<bool>>, key_bindings: KeyBindings) -> Self {
+        DemoApp {
+            images: Vec::new(),
+            show_demo: true,
+            show_debug_overlay: false,
+            debug_overlay: DebugOverlay::new(),
+            gpu_error: None,
+            dismiss_gpu_error: false,
+            console,
+            show_console: false,
+            depth_image: None,
+            show_depth: false,
+            refresh_depth: false,
+            live_depth: false,
+            show_picker: false,
+            picked_pixel: None,
+            start_capture_sequence: false,
+            capture_progress: None,
+            paused: false,
+            decorations: true,
+            always_on_top: false,
+            present_mode: wgpu::PresentMode::Fifo,
+            supported_present_modes: &[],
+            tonemapping: false,
+            show_particles: false,
+            wireframe: false,
+            exposure: 1.0,
+            clear_color: [0.0, 0.0, 0.0],
+            hovered_file: None,
+            theme: Theme::Dark,
+            fonts: NamedFonts::default(),
+            font_names: Vec::new(),
+            default_font: String::new(),
+            quit,
+            new_window,
+            reload_config,
+            key_bindings,
+        }
+    }
+
+    fn draw_gpu_error(&mut self, ui: &imgui::Ui) {
+        let (error, dismiss) = match &self.gpu_error {
+            Some(error) => (error, &mut self.dismiss_gpu_error),
+            None => return,
+        };
+        imgui::Window::new(im_str!("GPU error"))
+            .position([ui.io().display_size[0] / 2.0, ui.io().display_size[1] / 2.0], imgui::Condition::Appearing)
+            .position_pivot([0.5, 0.5])
+            .always_auto_resize(true)
+            .collapsible(false)
+            .build(ui, || {
+                ui.text_colored([1.0, 0.4, 0.4, 1.0], im_str!("wgpu reported an error:"));
+                ui.text_wrapped(&im_str!("{}", error));
+                if ui.button(im_str!("Copy"), [0.0, 0.0]) {
+                    ui.set_clipboard_text(&im_str!("{}", error));
+                }
+                ui.same_line(0.0);
+                if ui.button(im_str!("Dismiss"), [0.0, 0.0]) {
+                    *dismiss = true;
+                }
+            });
+    }
+
+    fn draw_depth(&mut self, ui: &imgui::Ui) {
+        let (image, refresh, live) = (self.depth_image, &mut self.refresh_depth, &mut self.live_depth);
+        imgui::Window::new(im_str!("Depth buffer"))
+            .size([340.0, 300.0], imgui::Condition::FirstUseEver)
+            .opened(&mut self.show_depth)
+            .build(ui, || {
+                if ui.button(im_str!("Refresh"), [0.0, 0.0]) {
+                    *refresh = true;
+                }
+                ui.same_line(0.0);
+                ui.checkbox(im_str!("Live"), live);
+                ui.text_disabled(im_str!("nearest is black, farthest white"));
+
+                if let Some((id, [width, height])) = image {
+                    // fit the window's width, keeping the aspect ratio
+                    let fit = ui.content_region_avail()[0].max(1.0);
+                    imgui::Image::new(id, [fit, fit * height / width]).build(ui);
+                }
+            });
+    }
+
+    fn draw_picker(&mut self, ui: &imgui::Ui) {
+        let picked = self.picked_pixel;
+        imgui::Window::new(im_str!("Pixel picker"))
+            .size([240.0, 90.0], imgui::Condition::FirstUseEver)
+            .opened(&mut self.show_picker)
+            .build(ui, || {
+                match picked {
+                    Some(([x, y], [r, g, b, a])) => {
+                        let color = [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0];
+                        imgui::ColorButton::new(im_str!("picked"), color).size([40.0, 40.0]).build(ui);
+                        ui.same_line(0.0);
+                        ui.group(|| {
+                            ui.text(format!("at {}, {}", x, y));
+                            ui.text(format!("rgba {} {} {} {}", r, g, b, a));
+                            ui.text(format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a));
+                        });
+                    },
+                    None => ui.text_disabled(im_str!("click anywhere outside the ui")),
+                }
+            });
+    }
+}
+
+// whatever's bound to `action`, for the menus
+fn shortcut_label(bindings: &KeyBindings, action: Action) -> imgui::ImString {
+    let keys: Vec<String> = bindings.keys_for(action).map(|combo| combo.to_string()).collect();
+    imgui::ImString::new(keys.join(", "))
+}
+
+impl App for DemoApp {
+    fn ui(&mut self, ui: &imgui::Ui, delta: Duration) {
+        let (show_demo, show_debug_overlay, show_console) = (&mut self.show_demo, &mut self.show_debug_overlay, &mut self.show_console);
+        let (show_depth, refresh_depth, show_particles) = (&mut self.show_depth, &mut self.refresh_depth, &mut self.show_particles);
+        let (wireframe, show_picker) = (&mut self.wireframe, &mut self.show_picker);
+        let (start_capture_sequence, capture_progress) = (&mut self.start_capture_sequence, self.capture_progress);
+        let (decorations, always_on_top) = (&mut self.decorations, &mut self.always_on_top);
+        let (theme, quit, new_window, reload_config) = (&mut self.theme, &self.quit, &self.new_window, &self.reload_config);
+        let key_bindings = &self.key_bindings;
+        let shortcut = |action| shortcut_label(key_bindings, action);
+        let mut open_about = false;
+        ui.main_menu_bar(|| {
+            ui.menu(im_str!("File"), true, || {
+                if imgui::MenuItem::new(im_str!("New window")).build(ui) {
+                    new_window.set(true);
+                }
+                match capture_progress {
+                    Some((written, count)) => {
+                        let label = imgui::ImString::new(format!("Capturing frame {}/{}", written + 1, count));
+                        imgui::MenuItem::new(&label).enabled(false).build(ui);
+                    },
+                    None => if imgui::MenuItem::new(&im_str!("Capture {} frames", CAPTURE_SEQUENCE_FRAMES)).build(ui) {
+                        *start_capture_sequence = true;
+                    },
+                }
+                if imgui::MenuItem::new(im_str!("Reload config")).shortcut(&shortcut(Action::ReloadConfig)).build(ui) {
+                    reload_config.set(true);
+                }
+                if imgui::MenuItem::new(im_str!("Quit")).shortcut(&shortcut(Action::Quit)).build(ui) {
+                    quit.set(true);
+                }
+            });
+            ui.menu(im_str!("View"), true, || {
+                imgui::MenuItem::new(im_str!("Demo window")).build_with_ref(ui, show_demo);
+                imgui::MenuItem::new(im_str!("Debug overlay")).shortcut(&shortcut(Action::ToggleDebugOverlay)).build_with_ref(ui, show_debug_overlay);
+                imgui::MenuItem::new(im_str!("Log")).build_with_ref(ui, show_console);
+                imgui::MenuItem::new(im_str!("Particles")).build_with_ref(ui, show_particles);
+                imgui::MenuItem::new(im_str!("Wireframe")).build_with_ref(ui, wireframe);
+                if imgui::MenuItem::new(im_str!("Depth buffer")).build_with_ref(ui, show_depth) {
+                    *refresh_depth = true;
+                }
+                imgui::MenuItem::new(im_str!("Pixel picker")).build_with_ref(ui, show_picker);
+                imgui::MenuItem::new(im_str!("Title bar")).shortcut(&shortcut(Action::ToggleDecorations)).build_with_ref(ui, decorations);
+                imgui::MenuItem::new(im_str!("Always on top")).shortcut(&shortcut(Action::ToggleAlwaysOnTop)).build_with_ref(ui, always_on_top);
+                ui.menu(im_str!("Theme"), true, || {
+                    for &choice in &Theme::ALL {
+                        let selected = *theme == choice;
+                        if imgui::MenuItem::new(&im_str!("{}", choice.name())).selected(selected).build(ui) {
+                            *theme = choice;
+                        }
+                    }
+                });
+            });
+            ui.menu(im_str!("Help"), true, || {
+                open_about = imgui::MenuItem::new(im_str!("About")).build(ui);
+            });
+        });
+
+        // popups are looked up by id relative to the current window, so this can't happen inside the menu
+        if open_about {
+            ui.open_popup(im_str!("About"));
+        }
+        ui.popup_modal(im_str!("About"))
+            .always_auto_resize(true)
+            .build(|| {
+                ui.text(im_str!("pepesilvia {}", env!("CARGO_PKG_VERSION")));
+                ui.text(im_str!("imgui-rs on wgpu"));
+                if ui.button(im_str!("Close"), [0.0, 0.0]) {
+                    ui.close_current_popup();
+                }
+            });
+
+        let window = imgui::Window::new(im_str!("Hello world"));
+        window
+            .size([300.0, 100.0], imgui::Condition::FirstUseEver)
+            .build(ui, || {
+                ui.text(im_str!("Hello world!"));
+                ui.text(im_str!("This...is...imgui-rs on WGPU!"));
+                ui.separator();
+                let mouse_pos = ui.io().mouse_pos;
+                ui.text(im_str!(
+                    "Mouse Position: ({:.1},{:.1})",
+                    mouse_pos[0],
+                    mouse_pos[1]
+                ));
+            });
+
+        let window = imgui::Window::new(im_str!("Hello too"));
+        window
+            .size([400.0, 200.0], imgui::Condition::FirstUseEver)
+            .position([400.0, 200.0], imgui::Condition::FirstUseEver)
+            .build(ui, || {
+                if self.paused {
+                    ui.text_colored([1.0, 0.8, 0.3, 1.0], im_str!("PAUSED (space to resume, right arrow to step)"));
+                }
+                // imgui's own delta is the smoothed one
+                ui.text(im_str!("Frametime: {:?} (smoothed {:.2} ms)", delta, ui.io().delta_time * 1000.0));
+                imgui::ColorEdit::new(im_str!("Background"), &mut self.clear_color).build(ui);
+                ui.checkbox(im_str!("Tonemapping"), &mut self.tonemapping);
+                if self.tonemapping {
+                    imgui::Slider::new(im_str!("Exposure")).range(0.0..=8.0).build(ui, &mut self.exposure);
+                }
+
+                let (fonts, font_names, default_font) = (&self.fonts, &self.font_names, &mut self.default_font);
+                imgui::ComboBox::new(im_str!("Font")).preview_value(&im_str!("{}", default_font)).build(ui, || {
+                    for name in font_names {
+                        // each one in itself, so they can be compared at a glance
+                        let token = fonts.push_font(ui, name);
+                        let clicked = imgui::Selectable::new(&im_str!("{}", name)).selected(name == default_font).build(ui);
+                        if let Some(token) = token {
+                            NamedFonts::pop_font(ui, token);
+                        }
+                        if clicked {
+                            default_font.clone_from(name);
+                        }
+                    }
+                });
+
+                let current = im_str!("{:?}", self.present_mode);
+                imgui::ComboBox::new(im_str!("Present mode")).preview_value(&current).build(ui, || {
+                    for &mode in &[wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate] {
+                        let clicked = imgui::Selectable::new(&im_str!("{:?}", mode))
+                            .selected(mode == self.present_mode)
+                            .disabled(!self.supported_present_modes.contains(&mode))
+                            .build(ui);
+                        if clicked {
+                            self.present_mode = mode;
+                        }
+                    }
+                });
+            });
+
+        if let Some(path) = &self.hovered_file {
+            let name = path.file_name().unwrap_or_else(|| path.as_os_str()).to_string_lossy();
+            imgui::Window::new(im_str!("Drop"))
+                .position([ui.io().display_size[0] / 2.0, ui.io().display_size[1] / 2.0], imgui::Condition::Always)
+                .position_pivot([0.5, 0.5])
+                .title_bar(false)
+                .always_auto_resize(true)
+                .build(ui, || {
+                    ui.text(im_str!("Drop to load {}", name));
+                });
+        }
+
+        if !self.images.is_empty() {
+            let images = &self.images;
+            imgui::Window::new(im_str!("Images"))
+                .size([300.0, 300.0], imgui::Condition::FirstUseEver)
+                .horizontal_scrollbar(true)
+                .build(ui, || {
+                    for image in images {
+                        imgui::Image::new(image.id, image.size).build(ui);
+                    }
+                });
+        }
+
+        if self.show_demo {
+            ui.show_demo_window(&mut self.show_demo);
+        }
+        if self.show_console {
+            self.console.draw(ui, &mut self.show_console);
+        }
+        if self.show_depth {
+            self.draw_depth(ui);
+        }
+        if self.show_picker {
+            self.draw_picker(ui);
+        }
+        self.draw_gpu_error(ui);
+    }
+}
+
+// loading the same file again (say, dropping it after editing it) reloads it in place
+pub(crate) fn load_image(imstate: &mut ImguiState, renderer: &Renderer, path: &Path, app: &mut DemoApp) {
+    if let Some(image) = app.images.iter_mut().find(|image| image.path == path) {
+        match imstate.replace_texture(image.id, renderer.device(), renderer.queue(), path) {
+            Ok(()) => image.size = imstate.texture_size(image.id).unwrap_or(image.size),
+            Err(e) => error!("{:?}", e),
+        }
+        return;
+    }
+
+    match imstate.load_texture(renderer.device(), renderer.queue(), path) {
+        Ok(id) => app.images.extend(imstate.texture_size(id).map(|size| LoadedImage { id, size, path: path.to_owned() })),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+// picks up whatever the ui changed about itself last frame
+pub(crate) fn sync_ui_settings(renderer: &Renderer, imstate: &mut ImguiState, app: &mut DemoApp) {
+    if app.theme != imstate.theme() {
+        imstate.set_theme(app.theme);
+    }
+    if app.default_font != imstate.default_font_name() {
+        if let Err(e) = imstate.set_default_font(renderer.device(), renderer.queue(), &app.default_font) {
+            error!("{:?}", e);
+        }
+    }
+    if app.font_names != imstate.font_names() {
+        app.font_names = imstate.font_names().to_vec();
+    }
+    if app.default_font != imstate.default_font_name() {
+        app.default_font = imstate.default_font_name().to_owned();
+    }
+}
+
+// reads back the frame that was just drawn, reusing the texture from last time
+pub(crate) fn update_depth_image(renderer: &mut Renderer, imstate: &mut ImguiState, app: &mut DemoApp) {
+    let image = match renderer.depth_image() {
+        Ok(image) => image,
+        Err(e) => {
+            // no point failing the same way every frame
+            app.live_depth = false;
+            error!("{:?}", e);
+            return;
+        },
+    };
+    let size = [image.width() as f32, image.height() as f32];
+
+    let id = match app.depth_image {
+        Some((id, _)) => match imstate.replace_rgba_texture(id, renderer.device(), renderer.queue(), &image) {
+            Ok(()) => id,
+            Err(e) => {
+                error!("{:?}", e);
+                return;
+            },
+        },
+        None => imstate.load_rgba_texture(renderer.device(), renderer.queue(), &image),
+    };
+    app.depth_image = Some((id, size));
+}
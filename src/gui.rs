@@ -0,0 +1,774 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Context, Result};
+use copypasta::ClipboardProvider;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::PhysicalSize,
+    error::ExternalError,
+    event::{Event, WindowEvent},
+    window::{CursorIcon, Window},
+};
+
+use crate::renderer::{Rect, Renderer};
+use crate::texture::Texture;
+
+pub struct ImguiState {
+    pub(crate) ctx: imgui::Context,
+    // None when headless, in which case nothing feeds it input
+    pub(crate) platform: Option<imgui_winit_support::WinitPlatform>,
+    pub(crate) renderer: imgui_wgpu::Renderer,
+    // fonts get rasterized at this multiple of their logical size so they stay sharp on hidpi
+    scale_factor: f64,
+    // everything in the atlas, so it can be rebuilt at a new scale, and what each one's called.
+    // the first is imgui's default
+    fonts: Vec<FontSpec>,
+    font_names: Vec<String>,
+    named_fonts: NamedFonts,
+    // a scale factor change we haven't rebuilt the atlas for yet, and when it came in
+    pending_scale_factor: Option<(f64, Instant)>,
+    // `Renderer::set_scale_override` as of the last frame; while it's set, reported changes are ignored
+    scale_override: Option<f64>,
+    theme: Theme,
+    // saved next to the layout, if that's being persisted
+    theme_path: Option<PathBuf>,
+    // everything from load_texture that hasn't been freed, so replace_texture can't make up new slots
+    textures: HashSet<imgui::TextureId>,
+    // top left of `Renderer::set_ui_viewport`'s rect in logical pixels, which the cursor is made relative to
+    ui_origin: [f32; 2],
+}
+
+/// imgui's built in color schemes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Dark,
+    Light,
+    Classic,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Dark, Theme::Light, Theme::Classic];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Dark => "Dark",
+            Theme::Light => "Light",
+            Theme::Classic => "Classic",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|theme| theme.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Fonts by name, shared with `ImguiState` (see `ImguiState::named_fonts`).
+#[derive(Clone, Default)]
+pub struct NamedFonts(Rc<RefCell<HashMap<String, imgui::FontId>>>);
+
+impl NamedFonts {
+    pub fn get(&self, name: &str) -> Option<imgui::FontId> {
+        self.0.borrow().get(name).copied()
+    }
+
+    /// Switches to the font called `name` until the token is handed to `pop_font`, or does nothing
+    /// if there isn't one.
+    pub fn push_font(&self, ui: &imgui::Ui, name: &str) -> Option<imgui::FontStackToken> {
+        self.get(name).map(|id| ui.push_font(id))
+    }
+
+    pub fn pop_font(ui: &imgui::Ui, token: imgui::FontStackToken) {
+        token.pop(ui);
+    }
+}
+
+// what add_font_with does to put things back
+enum Undo {
+    Unmerge(usize),
+    Restore(usize, FontSpec),
+    Remove,
+}
+
+// called "imgui" in font_names until something replaces it
+const BUILT_IN_FONT: &str = "imgui";
+
+// the biggest texture every backend can make
+const MAX_ATLAS_SIZE: u32 = 8192;
+
+/// How `ImguiState::add_font_with` loads a font.
+#[derive(Clone, Debug)]
+pub struct FontOptions {
+    /// Which characters to rasterize. imgui's default is only Latin-1, so CJK or icons need their own.
+    pub glyph_ranges: imgui::FontGlyphRanges,
+    /// Adds the glyphs to the font that already has the name, e.g. to put icons on top of the
+    /// base font, instead of making a separate one.
+    pub merge: bool,
+    /// `None` picks from the scale factor, see `Oversampling::for_scale_factor`. Merged glyphs
+    /// go with whatever the font they're merged into uses.
+    pub oversampling: Option<Oversampling>,
+}
+
+impl Default for FontOptions {
+    fn default() -> Self {
+        FontOptions { glyph_ranges: imgui::FontGlyphRanges::default(), merge: false, oversampling: None }
+    }
+}
+
+/// How finely imgui rasterizes a font's glyphs, which is what keeps them from looking crunchy
+/// when they land between pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Oversampling {
+    /// Horizontal and vertical subpixel samples, 1 to 8.
+    pub h: i32,
+    pub v: i32,
+    /// Rounds glyph positions to whole pixels, which is only crisp without horizontal oversampling.
+    pub pixel_snap_h: bool,
+}
+
+impl Oversampling {
+    /// Fonts get rasterized at the physical size, so on whole scale factors glyphs already land on
+    /// pixels and snapping them is sharpest. Fractional ones put them between pixels, which
+    /// needs the oversampling.
+    pub fn for_scale_factor(scale_factor: f64) -> Self {
+        if scale_factor.fract() == 0.0 {
+            Oversampling { h: 1, v: 1, pixel_snap_h: true }
+        } else {
+            Oversampling { h: 2, v: 1, pixel_snap_h: false }
+        }
+    }
+}
+
+// a font in the atlas, with anything merged into it
+struct FontSpec {
+    // None is imgui's built in font
+    base: Option<TtfSource>,
+    merged: Vec<TtfSource>,
+    // None goes by the scale factor the atlas is built at
+    oversampling: Option<Oversampling>,
+}
+
+struct TtfSource {
+    data: Vec<u8>,
+    size_px: f32,
+    glyph_ranges: imgui::FontGlyphRanges,
+}
+
+impl FontSpec {
+    const BUILT_IN: FontSpec = FontSpec { base: None, merged: Vec::new(), oversampling: None };
+
+    fn ttf(source: TtfSource, oversampling: Option<Oversampling>) -> Self {
+        FontSpec { base: Some(source), merged: Vec::new(), oversampling }
+    }
+}
+
+// dragging a window across monitors can fire a burst of scale changes; wait for them to settle
+const FONT_REBUILD_DEBOUNCE: Duration = Duration::from_millis(100);
+
+// the first four bytes of a truetype/opentype font or collection
+const FONT_MAGICS: &[&[u8; 4]] = &[b"\0\x01\0\0", b"true", b"OTTO", b"ttcf"];
+
+/// Where the imgui window layout gets saved, under the platform's config directory.
+pub fn default_layout_path() -> Option<PathBuf> {
+    directories::ProjectDirs::from("rs", "hecke", "pepesilvia")
+        .map(|dirs| dirs.config_dir().join("imgui.ini"))
+}
+
+impl ImguiState {
+    /// `layout_path` is where window positions are loaded from and saved to; `None` doesn't persist them.
+    pub fn new(window: &Window, renderer: &Renderer, layout_path: Option<PathBuf>) -> Self {
+        let mut ctx = imgui::Context::create();
+        let mut platform = imgui_winit_support::WinitPlatform::init(&mut ctx);
+        platform.attach_window(ctx.io_mut(),
+            window,
+            imgui_winit_support::HiDpiMode::Default);
+
+        // imgui just uses its defaults if the file isn't there yet, but it won't create the directory
+        let layout_path = layout_path.filter(|path| match path.parent().map(std::fs::create_dir_all) {
+            Some(Err(e)) => {
+                warn!("not persisting window layout, couldn't create the directory for {}: {}", path.display(), e);
+                false
+            },
+            _ => true,
+        });
+        let theme_path = layout_path.as_ref().map(|path| path.with_file_name("theme"));
+        ctx.set_ini_filename(layout_path);
+
+        ctx.set_clipboard_backend(system_clipboard());
+
+        let mut state = Self::with_context(ctx, Some(platform), renderer, window.scale_factor());
+        let saved_theme = theme_path.as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|name| Theme::from_name(name.trim()));
+        if let Some(theme) = saved_theme {
+            state.set_theme(theme);
+        }
+        state.theme_path = theme_path;
+        state
+    }
+
+    /// For a `Renderer::new_headless`; there's no window, so no input and no saved layout.
+    pub fn new_headless(renderer: &Renderer) -> Self {
+        let mut ctx = imgui::Context::create();
+        ctx.set_ini_filename(None);
+        ctx.io_mut().display_size = [renderer.size.width as f32, renderer.size.height as f32];
+
+        Self::with_context(ctx, None, renderer, 1.0)
+    }
+
+    fn with_context(
+        mut ctx: imgui::Context,
+        platform: Option<imgui_winit_support::WinitPlatform>,
+        renderer: &Renderer,
+        scale_factor: f64,
+    ) -> Self {
+        let rend_config = imgui_wgpu::RendererConfig::new().set_texture_format(renderer.swapchain_desc.format);
+        let renderer = imgui_wgpu::Renderer::new(&mut ctx, &renderer.device, &renderer.queue, rend_config);
+
+        ctx.io_mut().font_global_scale = (1.0/scale_factor) as f32;
+        add_font(&mut ctx, &FontSpec::BUILT_IN, scale_factor);
+
+        let mut state = ImguiState {
+            ctx,
+            platform,
+            renderer,
+            scale_factor,
+            fonts: vec![FontSpec::BUILT_IN],
+            font_names: vec![BUILT_IN_FONT.to_owned()],
+            named_fonts: NamedFonts::default(),
+            pending_scale_factor: None,
+            scale_override: None,
+            theme: Theme::Dark,
+            theme_path: None,
+            textures: HashSet::new(),
+            ui_origin: [0.0, 0.0],
+        };
+        state.update_named_fonts();
+        state
+    }
+
+    /// Recreates the gpu side of imgui on a `Renderer` that's been through `Renderer::recover`.
+    /// Textures from `load_texture` don't survive this and have to be loaded again.
+    pub fn recover(&mut self, renderer: &Renderer) {
+        let rend_config = imgui_wgpu::RendererConfig::new().set_texture_format(renderer.swapchain_desc.format);
+        self.renderer = imgui_wgpu::Renderer::new(&mut self.ctx, &renderer.device, &renderer.queue, rend_config);
+        self.textures.clear();
+    }
+
+    pub fn theme(&self) -> Theme {
+        self.theme
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        let style = self.ctx.style_mut();
+        match theme {
+            Theme::Dark => style.use_dark_colors(),
+            Theme::Light => style.use_light_colors(),
+            Theme::Classic => style.use_classic_colors(),
+        };
+        self.theme = theme;
+
+        if let Some(path) = &self.theme_path {
+            if let Err(e) = std::fs::write(path, theme.name()) {
+                warn!("couldn't save theme to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    pub fn load_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, path: &Path) -> Result<imgui::TextureId> {
+        let texture = self.read_texture(device, queue, path)?;
+        let id = self.renderer.textures.insert(texture);
+        self.textures.insert(id);
+        Ok(id)
+    }
+
+    /// Loads `path` into the slot of a texture from `load_texture`, so reloading an image doesn't use up a new id.
+    pub fn replace_texture(&mut self, id: imgui::TextureId, device: &wgpu::Device, queue: &wgpu::Queue, path: &Path) -> Result<()> {
+        if !self.textures.contains(&id) {
+            return Err(anyhow!("texture {} isn't loaded, nothing to replace", id.id()));
+        }
+
+        let texture = self.read_texture(device, queue, path)?;
+        self.renderer.textures.replace(id, texture);
+        Ok(())
+    }
+
+    /// Drops a texture from `load_texture`, returning whether it was loaded. Its id mustn't be drawn with afterwards.
+    pub fn free_texture(&mut self, id: imgui::TextureId) -> bool {
+        if !self.textures.remove(&id) {
+            return false;
+        }
+
+        self.renderer.textures.remove(id);
+        true
+    }
+
+    /// `free_texture` for everything from `load_texture`, e.g. before shutting down.
+    pub fn free_textures(&mut self) {
+        for id in self.textures.drain() {
+            self.renderer.textures.remove(id);
+        }
+    }
+
+    /// Ids of every texture from `load_texture` that's still around.
+    pub fn loaded_textures(&self) -> impl Iterator<Item = imgui::TextureId> + '_ {
+        self.textures.iter().copied()
+    }
+
+    /// `load_texture` for an image that's already in memory, like `Renderer::depth_image`.
+    pub fn load_rgba_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, image: &image::RgbaImage) -> imgui::TextureId {
+        let texture = self.upload_texture(device, queue, image);
+        let id = self.renderer.textures.insert(texture);
+        self.textures.insert(id);
+        id
+    }
+
+    /// `replace_texture` for an image that's already in memory. The size can change.
+    pub fn replace_rgba_texture(&mut self, id: imgui::TextureId, device: &wgpu::Device, queue: &wgpu::Queue, image: &image::RgbaImage) -> Result<()> {
+        if !self.textures.contains(&id) {
+            return Err(anyhow!("texture {} isn't loaded, nothing to replace", id.id()));
+        }
+
+        let texture = self.upload_texture(device, queue, image);
+        self.renderer.textures.replace(id, texture);
+        Ok(())
+    }
+
+    fn read_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue, path: &Path) -> Result<imgui_wgpu::Texture> {
+        let image = image::ImageReader::open(path)
+            .with_context(|| format!("couldn't open {}", path.display()))?
+            .with_guessed_format()
+            .with_context(|| format!("couldn't read {}", path.display()))?
+            .decode()
+            .with_context(|| format!("couldn't decode {} as an image", path.display()))?;
+
+        Ok(imgui_texture(device, Texture::from_image(device, queue, &image, "imgui image texture")))
+    }
+
+    fn upload_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue, image: &image::RgbaImage) -> imgui_wgpu::Texture {
+        imgui_texture(device, Texture::from_rgba(device, queue, image, "imgui image texture"))
+    }
+
+    /// Loads a ttf/otf font at `size_px` logical pixels. This doesn't make it the default; push the returned id to use it.
+    /// It's named after the file, see `add_named_font`.
+    pub fn add_ttf_font(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, path: &Path, size_px: f32) -> Result<imgui::FontId> {
+        let name = font_name(path);
+        self.add_named_font(device, queue, &name, path, size_px)?;
+        self.named_fonts.get(&name).ok_or_else(|| anyhow!("{} went missing from the font atlas", name))
+    }
+
+    /// Loads a ttf/otf font at `size_px` logical pixels under `name`, replacing any font already
+    /// called that. Switch to it inside `App::ui` with `NamedFonts::push_font`.
+    pub fn add_named_font(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, name: &str, path: &Path, size_px: f32) -> Result<()> {
+        self.add_font_with(device, queue, name, path, size_px, FontOptions::default())
+    }
+
+    /// `add_named_font` with a choice of glyphs, and optionally merged into the font called `name`
+    /// (the built in one is "imgui") rather than replacing it. Fails, leaving the fonts as they
+    /// were, if the atlas would get too big for a texture.
+    pub fn add_font_with(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        name: &str,
+        path: &Path,
+        size_px: f32,
+        options: FontOptions,
+    ) -> Result<()> {
+        let source = read_ttf_font(path, size_px, options.glyph_ranges)?;
+        let existing = self.font_names.iter().position(|existing| existing == name);
+
+        // so the atlas can go back to how it was if this doesn't fit
+        let undo = match (existing, options.merge) {
+            (Some(index), true) => {
+                self.fonts[index].merged.push(source);
+                Undo::Unmerge(index)
+            },
+            (None, true) => return Err(anyhow!("there's no font called {:?} to merge {} into", name, path.display())),
+            (Some(index), false) => Undo::Restore(index, std::mem::replace(&mut self.fonts[index], FontSpec::ttf(source, options.oversampling))),
+            (None, false) => {
+                self.fonts.push(FontSpec::ttf(source, options.oversampling));
+                self.font_names.push(name.to_owned());
+                Undo::Remove
+            },
+        };
+
+        if let Err((width, height)) = self.build_atlas(self.scale_factor) {
+            match undo {
+                Undo::Unmerge(index) => drop(self.fonts[index].merged.pop()),
+                Undo::Restore(index, font) => self.fonts[index] = font,
+                Undo::Remove => {
+                    self.fonts.pop();
+                    self.font_names.pop();
+                },
+            }
+            self.rebuild_fonts(device, queue, self.scale_factor);
+            return Err(anyhow!("adding {} would make the font atlas {}x{}, over the {} limit; try fewer glyph ranges", path.display(), width, height, MAX_ATLAS_SIZE));
+        }
+
+        // it fit, so what was just built is what gets uploaded
+        self.upload_atlas(device, queue);
+        Ok(())
+    }
+
+    /// Overrides how the font called `name` is oversampled, or goes back to picking from the scale
+    /// factor with `None`, and rebuilds the atlas.
+    pub fn set_font_oversampling(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, name: &str, oversampling: Option<Oversampling>) -> Result<()> {
+        let index = self.font_names.iter().position(|existing| existing == name)
+            .ok_or_else(|| anyhow!("there's no font called {:?}", name))?;
+        if self.fonts[index].oversampling != oversampling {
+            self.fonts[index].oversampling = oversampling;
+            self.rebuild_fonts(device, queue, self.scale_factor);
+        }
+        Ok(())
+    }
+
+    /// What the font called `name` is oversampled with at the current scale factor.
+    pub fn font_oversampling(&self, name: &str) -> Option<Oversampling> {
+        let index = self.font_names.iter().position(|existing| existing == name)?;
+        Some(self.fonts[index].oversampling.unwrap_or_else(|| Oversampling::for_scale_factor(self.scale_factor)))
+    }
+
+    /// Replaces imgui's built in font with a ttf/otf one at `size_px` logical pixels.
+    pub fn set_default_ttf_font(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, path: &Path, size_px: f32) -> Result<()> {
+        // imgui uses the first font in the atlas unless told otherwise
+        self.fonts[0].base = Some(read_ttf_font(path, size_px, imgui::FontGlyphRanges::default())?);
+        self.font_names[0] = font_name(path);
+        self.rebuild_fonts(device, queue, self.scale_factor);
+        Ok(())
+    }
+
+    /// Makes the font called `name` the one imgui uses when nothing's been pushed.
+    pub fn set_default_font(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, name: &str) -> Result<()> {
+        let index = self.font_names.iter().position(|existing| existing == name)
+            .ok_or_else(|| anyhow!("there's no font called {:?}", name))?;
+        if index != 0 {
+            let font = self.fonts.remove(index);
+            self.fonts.insert(0, font);
+            let name = self.font_names.remove(index);
+            self.font_names.insert(0, name);
+            self.rebuild_fonts(device, queue, self.scale_factor);
+        }
+        Ok(())
+    }
+
+    pub fn default_font_name(&self) -> &str {
+        &self.font_names[0]
+    }
+
+    /// Every font's name, default first. The built in one is called "imgui" until it's replaced.
+    pub fn font_names(&self) -> &[String] {
+        &self.font_names
+    }
+
+    /// A handle for looking fonts up by name from inside `App::ui`, which stays up to date as fonts
+    /// are added and the atlas is rebuilt.
+    pub fn named_fonts(&self) -> NamedFonts {
+        self.named_fonts.clone()
+    }
+
+    /// Every font in the atlas, in the order they were added. Rebuilding the atlas for a new
+    /// scale factor makes new fonts, so ids from before a dpi change have to be looked up again here.
+    pub fn fonts(&mut self) -> Vec<imgui::FontId> {
+        self.ctx.fonts().fonts()
+    }
+
+    // re-rasterizes every font for `scale_factor` and reuploads the atlas, dropping the newest
+    // fonts if it won't fit in a texture
+    fn rebuild_fonts(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+        self.ctx.io_mut().font_global_scale = (1.0/scale_factor) as f32;
+
+        while let Err((width, height)) = self.build_atlas(scale_factor) {
+            if self.fonts.len() == 1 {
+                break;
+            }
+            self.fonts.pop();
+            let dropped = self.font_names.pop().unwrap_or_default();
+            warn!("font atlas would be {}x{}, over the {} limit; dropping {:?}", width, height, MAX_ATLAS_SIZE, dropped);
+        }
+
+        self.upload_atlas(device, queue);
+    }
+
+    // uploads the atlas build_atlas last built; imgui hands back the same pixels rather than
+    // building it again, as long as nothing's cleared it since
+    fn upload_atlas(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.renderer.reload_font_texture(&mut self.ctx, device, queue);
+        self.update_named_fonts();
+    }
+
+    // lays out every font at `scale_factor`, or returns how big the atlas would be if that's too big
+    fn build_atlas(&mut self, scale_factor: f64) -> Result<(), (u32, u32)> {
+        self.ctx.fonts().clear();
+        for font in &self.fonts {
+            add_font(&mut self.ctx, font, scale_factor);
+        }
+
+        let mut atlas = self.ctx.fonts();
+        let texture = atlas.build_rgba32_texture();
+        if texture.width <= MAX_ATLAS_SIZE && texture.height <= MAX_ATLAS_SIZE {
+            Ok(())
+        } else {
+            Err((texture.width, texture.height))
+        }
+    }
+
+    fn update_named_fonts(&mut self) {
+        let ids = self.ctx.fonts().fonts();
+        let mut named = self.named_fonts.0.borrow_mut();
+        named.clear();
+        // if two fonts share a name, the first one wins
+        for (name, id) in self.font_names.iter().zip(ids) {
+            named.entry(name.clone()).or_insert(id);
+        }
+    }
+
+    pub fn texture_size(&self, id: imgui::TextureId) -> Option<[f32; 2]> {
+        self.renderer.textures.get(id)
+            .map(|texture| [texture.width() as f32, texture.height() as f32])
+    }
+
+    /// Whether imgui is using the keyboard (e.g. a text field has focus), so key bindings shouldn't fire.
+    pub fn wants_keyboard(&self) -> bool {
+        self.ctx.io().want_capture_keyboard
+    }
+
+    /// Whether imgui is using the mouse (e.g. it's over a window), so it shouldn't move the camera.
+    pub fn wants_mouse(&self) -> bool {
+        self.ctx.io().want_capture_mouse
+    }
+
+    /// Both of the above at once, as `(mouse, keyboard)`. App input of either kind should only be
+    /// handled when imgui isn't capturing it.
+    pub fn wants_input(&self) -> (bool, bool) {
+        let io = self.ctx.io();
+        (io.want_capture_mouse, io.want_capture_keyboard)
+    }
+
+    /// Whether there's something (like a font rebuild) waiting on the next frame, even if no events come in.
+    pub fn has_pending_work(&self) -> bool {
+        self.pending_scale_factor.is_some()
+    }
+
+    pub fn handle_event<T>(&mut self, window: &Window, event: &Event<T>) {
+        if let Event::WindowEvent { event: WindowEvent::ScaleFactorChanged { scale_factor, .. }, window_id } = event {
+            if *window_id == window.id() && self.scale_override.is_none() {
+                self.pending_scale_factor = Some((*scale_factor, Instant::now()));
+            }
+        }
+
+        if let Some(platform) = &mut self.platform {
+            platform.handle_event(self.ctx.io_mut(), window, event);
+        }
+
+        // the platform only knows about the window, so move the cursor into the ui's viewport
+        if let Event::WindowEvent { event: WindowEvent::CursorMoved { .. }, window_id } = event {
+            if *window_id == window.id() {
+                let mouse_pos = &mut self.ctx.io_mut().mouse_pos;
+                mouse_pos[0] -= self.ui_origin[0];
+                mouse_pos[1] -= self.ui_origin[1];
+            }
+        }
+    }
+
+    pub(crate) fn prepare_frame(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        window: Option<&Window>,
+        size: PhysicalSize<u32>,
+        viewport: Option<Rect>,
+        scale_override: Option<f64>,
+    ) -> Result<(), ExternalError> {
+        if scale_override != self.scale_override {
+            self.scale_override = scale_override;
+            // locking the platform's factor is what makes it ignore the window's for input and sizes
+            if let (Some(platform), Some(window)) = (&mut self.platform, window) {
+                let mode = scale_override.map_or(imgui_winit_support::HiDpiMode::Default, imgui_winit_support::HiDpiMode::Locked);
+                platform.attach_window(self.ctx.io_mut(), window, mode);
+            }
+            self.pending_scale_factor = None;
+            let scale_factor = scale_override.unwrap_or_else(|| window.map_or(1.0, Window::scale_factor));
+            if scale_factor != self.scale_factor {
+                self.rebuild_fonts(device, queue, scale_factor);
+            }
+        }
+
+        if let Some((scale_factor, changed_at)) = self.pending_scale_factor {
+            if changed_at.elapsed() >= FONT_REBUILD_DEBOUNCE {
+                self.pending_scale_factor = None;
+                if scale_factor != self.scale_factor {
+                    self.rebuild_fonts(device, queue, scale_factor);
+                }
+            }
+        }
+
+        match (&mut self.platform, window) {
+            (Some(platform), Some(window)) => platform.prepare_frame(self.ctx.io_mut(), window)?,
+            _ => self.ctx.io_mut().display_size = [size.width as f32, size.height as f32],
+        }
+
+        let io = self.ctx.io_mut();
+        let [scale_x, scale_y] = io.display_framebuffer_scale;
+        let origin = match viewport {
+            Some(rect) => {
+                io.display_size = [rect.width as f32 / scale_x, rect.height as f32 / scale_y];
+                [rect.x as f32 / scale_x, rect.y as f32 / scale_y]
+            },
+            None => [0.0, 0.0],
+        };
+        // the cursor was made relative to the old origin when it moved, so shift it over
+        if origin != self.ui_origin && io.mouse_pos[0] != -f32::MAX {
+            io.mouse_pos[0] += self.ui_origin[0] - origin[0];
+            io.mouse_pos[1] += self.ui_origin[1] - origin[1];
+        }
+        self.ui_origin = origin;
+        Ok(())
+    }
+}
+
+// imgui only knows how to reach the system clipboard on windows
+struct SystemClipboard(copypasta::ClipboardContext);
+
+impl imgui::ClipboardBackend for SystemClipboard {
+    fn get(&mut self) -> Option<imgui::ImString> {
+        self.0.get_contents().ok().map(imgui::ImString::new)
+    }
+
+    fn set(&mut self, value: &imgui::ImStr) {
+        if let Err(e) = self.0.set_contents(value.to_str().to_owned()) {
+            warn!("couldn't copy to the clipboard: {}", e);
+        }
+    }
+}
+
+// for when there's no clipboard to talk to (no display server, say)
+struct NoClipboard;
+
+impl imgui::ClipboardBackend for NoClipboard {
+    fn get(&mut self) -> Option<imgui::ImString> {
+        None
+    }
+
+    fn set(&mut self, _: &imgui::ImStr) {}
+}
+
+fn system_clipboard() -> Box<dyn imgui::ClipboardBackend> {
+    match copypasta::ClipboardContext::new() {
+        Ok(clipboard) => Box::new(SystemClipboard(clipboard)),
+        Err(e) => {
+            warn!("copy and paste won't work, couldn't open the clipboard: {}", e);
+            Box::new(NoClipboard)
+        },
+    }
+}
+
+// wraps `texture` up for imgui-wgpu. its texture bind group layout is private, but wgpu hands back
+// the same layout for an identical descriptor, so this one is compatible with its pipeline
+fn imgui_texture(device: &wgpu::Device, texture: Texture) -> imgui_wgpu::Texture {
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("imgui image bind group layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    component_type: wgpu::TextureComponentType::Float,
+                    dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Sampler { comparison: false },
+                count: None,
+            },
+        ],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("imgui image bind group"),
+        layout: &layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            },
+        ],
+    });
+
+    let Texture { texture, view, size, .. } = texture;
+    imgui_wgpu::Texture::from_raw_parts(texture, view, bind_group, size)
+}
+
+// what the os should show for imgui's cursor, the same as imgui-winit-support picks
+pub(crate) fn winit_cursor(cursor: imgui::MouseCursor) -> CursorIcon {
+    match cursor {
+        imgui::MouseCursor::Arrow => CursorIcon::Arrow,
+        imgui::MouseCursor::TextInput => CursorIcon::Text,
+        imgui::MouseCursor::ResizeAll => CursorIcon::Move,
+        imgui::MouseCursor::ResizeNS => CursorIcon::NsResize,
+        imgui::MouseCursor::ResizeEW => CursorIcon::EwResize,
+        imgui::MouseCursor::ResizeNESW => CursorIcon::NeswResize,
+        imgui::MouseCursor::ResizeNWSE => CursorIcon::NwseResize,
+        imgui::MouseCursor::Hand => CursorIcon::Hand,
+        imgui::MouseCursor::NotAllowed => CursorIcon::NotAllowed,
+    }
+}
+
+fn font_name(path: &Path) -> String {
+    path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
+
+fn read_ttf_font(path: &Path, size_px: f32, glyph_ranges: imgui::FontGlyphRanges) -> Result<TtfSource> {
+    let data = std::fs::read(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    // imgui asserts instead of returning an error when stb_truetype chokes, so catch obvious garbage here
+    if !FONT_MAGICS.iter().any(|magic| data.starts_with(&magic[..])) {
+        return Err(anyhow!("{} doesn't look like a truetype or opentype font", path.display()));
+    }
+
+    Ok(TtfSource { data, size_px, glyph_ranges })
+}
+
+fn add_font(ctx: &mut imgui::Context, font: &FontSpec, scale_factor: f64) -> imgui::FontId {
+    fn ttf(source: &TtfSource, scale_factor: f64, oversampling: Oversampling) -> imgui::FontSource<'_> {
+        imgui::FontSource::TtfData {
+            data: &source.data,
+            size_pixels: source.size_px * scale_factor as f32,
+            config: Some(imgui::FontConfig {
+                glyph_ranges: source.glyph_ranges.clone(),
+                oversample_h: oversampling.h,
+                oversample_v: oversampling.v,
+                pixel_snap_h: oversampling.pixel_snap_h,
+                ..Default::default()
+            }),
+        }
+    }
+
+    let oversampling = font.oversampling.unwrap_or_else(|| Oversampling::for_scale_factor(scale_factor));
+    let mut sources = vec![match &font.base {
+        None => imgui::FontSource::DefaultFontData {
+            config: Some(imgui::FontConfig {
+                oversample_h: oversampling.h,
+                oversample_v: oversampling.v,
+                pixel_snap_h: oversampling.pixel_snap_h,
+                size_pixels: (13. * scale_factor) as f32,
+                ..Default::default()
+            }),
+        },
+        Some(base) => ttf(base, scale_factor, oversampling),
+    }];
+    // imgui merges everything after the first source into it
+    sources.extend(font.merged.iter().map(|source| ttf(source, scale_factor, oversampling)));
+    ctx.fonts().add_font(&sources)
+}
@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use anyhow::{anyhow, Result};
+use serde::{de::value::StrDeserializer, Deserialize, Deserializer, Serialize, Serializer};
+use winit::event::{ModifiersState, VirtualKeyCode};
+
+/// Something a shortcut can do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    CyclePresentMode,
+    ToggleFullscreen,
+    ToggleDecorations,
+    ToggleAlwaysOnTop,
+    Screenshot,
+    Pause,
+    /// Advance one frame while paused.
+    Step,
+    /// Read the config file again and apply whatever can change without a restart.
+    ReloadConfig,
+    ToggleDebugOverlay,
+    Quit,
+}
+
+/// A key plus exactly the modifiers that have to be held with it, written like `Ctrl+Shift+S`.
+/// Key names are winit's `VirtualKeyCode` variants (`F11`, `Space`, `Key1`, ...).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KeyCombo {
+    pub key: VirtualKeyCode,
+    pub modifiers: ModifiersState,
+}
+
+impl KeyCombo {
+    pub fn new(key: VirtualKeyCode) -> Self {
+        KeyCombo { key, modifiers: ModifiersState::empty() }
+    }
+
+    pub fn with(self, modifiers: ModifiersState) -> Self {
+        KeyCombo { modifiers: self.modifiers | modifiers, ..self }
+    }
+}
+
+const MODIFIER_NAMES: [(ModifiersState, &str); 4] = [
+    (ModifiersState::CTRL, "Ctrl"),
+    (ModifiersState::SHIFT, "Shift"),
+    (ModifiersState::ALT, "Alt"),
+    (ModifiersState::LOGO, "Logo"),
+];
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, name) in MODIFIER_NAMES.iter() {
+            if self.modifiers.contains(*modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        // the Debug output of a fieldless variant is its name, which is also what serde uses
+        write!(f, "{:?}", self.key)
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+        let key = VirtualKeyCode::deserialize(StrDeserializer::<serde::de::value::Error>::new(key))
+            .map_err(|_| anyhow!("{:?} isn't a key name", key))?;
+
+        let mut modifiers = ModifiersState::empty();
+        for part in parts {
+            let (modifier, _) = MODIFIER_NAMES.iter()
+                .find(|(_, name)| name.eq_ignore_ascii_case(part))
+                .ok_or_else(|| anyhow!("{:?} isn't a modifier in {:?}", part, s))?;
+            modifiers |= *modifier;
+        }
+
+        Ok(KeyCombo { key, modifiers })
+    }
+}
+
+impl Serialize for KeyCombo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyCombo {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Which key does what, as the `[keys]` table of the config: `"Ctrl+S" = "screenshot"`.
+/// A table in the config replaces the defaults entirely, so leaving a key out unbinds it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct KeyBindings {
+    bindings: BTreeMap<KeyCombo, Action>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        use VirtualKeyCode::*;
+        let bindings = vec![
+            (KeyCombo::new(V), Action::CyclePresentMode),
+            (KeyCombo::new(F11), Action::ToggleFullscreen),
+            (KeyCombo::new(F10), Action::ToggleDecorations),
+            (KeyCombo::new(F9), Action::ToggleAlwaysOnTop),
+            (KeyCombo::new(F12), Action::Screenshot),
+            (KeyCombo::new(S).with(ModifiersState::CTRL), Action::Screenshot),
+            (KeyCombo::new(Space), Action::Pause),
+            (KeyCombo::new(Right), Action::Step),
+            (KeyCombo::new(R).with(ModifiersState::CTRL), Action::ReloadConfig),
+            (KeyCombo::new(F1), Action::ToggleDebugOverlay),
+            (KeyCombo::new(Q).with(ModifiersState::CTRL), Action::Quit),
+        ];
+        KeyBindings { bindings: bindings.into_iter().collect() }
+    }
+}
+
+impl KeyBindings {
+    /// What pressing `key` with exactly `modifiers` held should do, if anything.
+    pub fn action(&self, key: VirtualKeyCode, modifiers: ModifiersState) -> Option<Action> {
+        self.bindings.get(&KeyCombo { key, modifiers }).copied()
+    }
+
+    pub fn bind(&mut self, combo: KeyCombo, action: Action) {
+        self.bindings.insert(combo, action);
+    }
+
+    pub fn unbind(&mut self, combo: KeyCombo) -> Option<Action> {
+        self.bindings.remove(&combo)
+    }
+
+    /// Every key bound to `action`, e.g. for showing next to a menu item.
+    pub fn keys_for(&self, action: Action) -> impl Iterator<Item = KeyCombo> + '_ {
+        self.bindings.iter().filter(move |(_, bound)| **bound == action).map(|(combo, _)| *combo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combos_round_trip_through_strings() {
+        for text in &["S", "F11", "Ctrl+S", "Ctrl+Shift+Key1", "Ctrl+Shift+Alt+Logo+Space"] {
+            let combo: KeyCombo = text.parse().unwrap();
+            assert_eq!(combo.to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn modifiers_are_case_insensitive_and_tolerate_spaces() {
+        let combo: KeyCombo = "ctrl + SHIFT + S".parse().unwrap();
+        assert_eq!(combo, KeyCombo::new(VirtualKeyCode::S).with(ModifiersState::CTRL | ModifiersState::SHIFT));
+        assert_eq!(combo.to_string(), "Ctrl+Shift+S");
+    }
+
+    #[test]
+    fn malformed_combos_are_rejected() {
+        for text in &["", "Ctrl+", "Hyper+S", "NotAKey", "Ctrl+s", "S+Ctrl"] {
+            assert!(text.parse::<KeyCombo>().is_err(), "{:?} parsed", text);
+        }
+    }
+
+    #[test]
+    fn actions_need_exactly_their_modifiers() {
+        let bindings = KeyBindings::default();
+        assert_eq!(bindings.action(VirtualKeyCode::S, ModifiersState::CTRL), Some(Action::Screenshot));
+        assert_eq!(bindings.action(VirtualKeyCode::S, ModifiersState::CTRL | ModifiersState::SHIFT), None);
+        assert_eq!(bindings.action(VirtualKeyCode::S, ModifiersState::empty()), None);
+        assert_eq!(bindings.action(VirtualKeyCode::Space, ModifiersState::empty()), Some(Action::Pause));
+    }
+
+    #[test]
+    fn binding_replaces_and_unbinding_removes() {
+        let mut bindings = KeyBindings::default();
+        let combo = KeyCombo::new(VirtualKeyCode::Space);
+        bindings.bind(combo, Action::Step);
+        assert_eq!(bindings.action(VirtualKeyCode::Space, ModifiersState::empty()), Some(Action::Step));
+        assert_eq!(bindings.unbind(combo), Some(Action::Step));
+        assert_eq!(bindings.action(VirtualKeyCode::Space, ModifiersState::empty()), None);
+        assert_eq!(bindings.keys_for(Action::Pause).count(), 0);
+    }
+
+    #[test]
+    fn bindings_read_from_a_config_table() {
+        let bindings: KeyBindings = toml::from_str("\"Ctrl+P\" = \"pause\"\nF5 = \"reload_config\"\n").unwrap();
+        assert_eq!(bindings.action(VirtualKeyCode::P, ModifiersState::CTRL), Some(Action::Pause));
+        assert_eq!(bindings.action(VirtualKeyCode::F5, ModifiersState::empty()), Some(Action::ReloadConfig));
+        // a table replaces the defaults
+        assert_eq!(bindings.action(VirtualKeyCode::Space, ModifiersState::empty()), None);
+        assert!(toml::from_str::<KeyBindings>("\"Ctrl+\" = \"pause\"").is_err());
+    }
+}
@@ -0,0 +1,30 @@
+mod app;
+mod renderer;
+mod gui;
+mod triangle;
+mod particles;
+mod blit;
+mod tonemap;
+mod capture;
+mod console;
+mod camera;
+mod shader_watch;
+mod stats;
+mod texture;
+mod config;
+mod keys;
+mod watchdog;
+mod debug_overlay;
+
+pub use app::App;
+pub use console::LogConsole;
+pub use camera::Camera;
+pub use shader_watch::ShaderWatcher;
+pub use stats::{AdapterReport, FrameStats, StatsReport};
+pub use texture::Texture;
+pub use config::{Backend, Config, OutputFormat, default_config_path};
+pub use keys::{Action, KeyBindings, KeyCombo};
+pub use watchdog::{block_on_timeout, block_on_timeout_or};
+pub use debug_overlay::DebugOverlay;
+pub use renderer::{DeltaSmoothing, FrameContext, HDR_FORMAT, Rect, Renderer, RendererBuilder, RendererConfig, RenderError, ResizeCallback, TimeSource, UserPass, enumerate_adapters, linear_to_srgb, srgb_to_linear};
+pub use gui::{FontOptions, ImguiState, NamedFonts, Oversampling, Theme, default_layout_path};
@@ -1,250 +1,1103 @@
-use std::time::Instant;
-use thiserror::Error;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use anyhow::{anyhow, Context, Result};
-use log::{debug, error};
-use futures::executor::block_on;
+use clap::{Parser, ValueEnum};
+use log::{error, info, warn, LevelFilter};
+use serde::{Deserialize, Serialize};
 use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
     event::*,
-    event_loop::{EventLoop, ControlFlow},
-    window::{Window, WindowBuilder},
-    dpi::PhysicalSize,
+    event_loop::{EventLoop, EventLoopWindowTarget, ControlFlow},
+    window::{CursorIcon, Fullscreen, Icon, Window, WindowBuilder, WindowId},
 };
-use imgui::im_str;
-
-#[allow(dead_code)]
-struct Renderer {
-    instance: wgpu::Instance,
-    surface: wgpu::Surface,
-    adapter: wgpu::Adapter,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    swapchain_desc: wgpu::SwapChainDescriptor,
-    swapchain: wgpu::SwapChain,
+
+mod demo;
+
+use demo::{DemoApp, load_image, sync_ui_settings, update_depth_image};
+use pepesilvia::{App, Action, Backend, Config, OutputFormat, LogConsole, Renderer, RendererConfig, RenderError, ImguiState, TimeSource, block_on_timeout_or, default_config_path, default_layout_path, enumerate_adapters, linear_to_srgb, srgb_to_linear};
+
+// how many times in a row we'll rebuild a lost/outdated swapchain before giving up
+const MAX_SWAPCHAIN_RECREATIONS: u32 = 2;
+// and how many times in a row we'll rebuild the whole renderer after that, assuming the device was lost
+const MAX_DEVICE_RECOVERIES: u32 = 1;
+
+// tiny swapchains are pointless and some drivers get unhappy about them. winit ignores this on
+// the web and mobile, where the platform owns the window size anyway
+const MIN_WINDOW_SIZE: (u32, u32) = (320, 240);
+const TITLE_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+// dragging a window edge sends a Resized for nearly every pixel, and each one rebuilds the
+// swapchain and render targets. wait until the size has held still this long first
+const RESIZE_DEBOUNCE: Duration = Duration::from_millis(50);
+
+// where --watch-shaders looks; only makes sense when running from the source tree
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+
+// imgui lays some things out from the previous frame's sizes (menus, auto-resizing windows), so
+// keep drawing for a few frames after anything happens to let it settle before going idle
+const SETTLE_FRAMES: u32 = 3;
+// two seconds at 30fps, which is plenty for a gif
+const CAPTURE_SEQUENCE_FRAMES: u32 = 60;
+// what each --headless-frames frame advances by, so the animation ends up the same every run
+const HEADLESS_FRAME_STEP: Duration = Duration::from_nanos(16_666_667);
+
+#[derive(Parser)]
+#[command(about = "imgui on wgpu")]
+// anything left unset here comes from the config file
+struct Args {
+    /// Log more to stderr, up to -vvvv for everything; RUST_LOG overrides this
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Settings file to use instead of the one in the config directory
+    #[arg(long)]
+    config: Option<PathBuf>,
+    #[arg(long)]
+    title: Option<String>,
+    /// Image to use as the window icon
+    #[arg(long)]
+    icon: Option<PathBuf>,
+    /// Initial window width, in physical pixels
+    #[arg(long)]
+    width: Option<u32>,
+    /// Initial window height, in physical pixels
+    #[arg(long)]
+    height: Option<u32>,
+    /// Which wgpu backends to look for an adapter on
+    #[arg(long, value_enum)]
+    backend: Option<Backend>,
+    /// Use this adapter (as numbered by --list-adapters) instead of letting wgpu pick
+    #[arg(long)]
+    adapter_index: Option<usize>,
+    /// Format to draw the window in; not every backend can present all of them
+    #[arg(long, value_enum)]
+    output_format: Option<OutputFormat>,
+    /// Render on a software adapter like llvmpipe, for output that's the same on any machine
+    #[arg(long)]
+    force_fallback: bool,
+    /// Ask as little of the gpu as possible (low power adapter, no msaa, vsync on), for old or
+    /// integrated ones that fail to start otherwise
+    #[arg(long)]
+    safe_mode: bool,
+    /// Record a wgpu api trace into this directory (needs the trace feature)
+    #[arg(long)]
+    trace_dir: Option<PathBuf>,
+    /// Print the adapters on the chosen backends and exit
+    #[arg(long)]
+    list_adapters: bool,
+    /// Off presents immediately, tearing and all
+    #[arg(long, value_enum)]
+    vsync: Option<Vsync>,
+    /// Fraction of the window resolution to draw the scene at, upscaled afterwards
+    #[arg(long)]
+    render_scale: Option<f32>,
+    /// Scale factor to use instead of the one the display reports, when that one's wrong
+    #[arg(long)]
+    scale_factor: Option<f64>,
+    /// Frames per second to cap rendering at
+    #[arg(long)]
+    frame_cap: Option<u32>,
+    /// Render this many frames offscreen at a fixed 60fps step without opening a window, print
+    /// timings and exit
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    headless_frames: Option<u32>,
+    /// Where --headless-frames writes its timings as json, for tracking them across commits
+    #[arg(long, default_value = "frame-stats.json")]
+    stats_json: PathBuf,
+    /// Don't load or save the imgui window layout
+    #[arg(long)]
+    no_persist_layout: bool,
+    /// Make the window background see-through where nothing's drawn. Needs a compositor, and with
+    /// this wgpu most platforms still show it opaque; tonemapping also has to stay off
+    #[arg(long)]
+    transparent: bool,
+    /// Draw just the scene, without imgui; shortcuts and the camera still work
+    #[arg(long)]
+    no_ui: bool,
+    /// Seconds to wait for the gpu adapter and device before giving up
+    #[arg(long)]
+    init_timeout: Option<u64>,
+    /// Rebuild the triangle whenever its SPIR-V in src/shaders changes
+    #[arg(long)]
+    watch_shaders: bool,
+    /// An image to show in the ui
+    image: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Vsync {
+    On,
+    Off,
+}
+
+// flags given on the command line win over the config file
+fn apply_args(config: &mut Config, args: &Args) {
+    if let Some(title) = &args.title {
+        config.title = title.clone();
+    }
+    if let Some(width) = args.width {
+        config.width = width;
+    }
+    if let Some(height) = args.height {
+        config.height = height;
+    }
+    if let Some(backend) = args.backend {
+        config.backend = backend;
+    }
+    if let Some(secs) = args.init_timeout {
+        config.init_timeout_secs = secs;
+    }
+    if args.output_format.is_some() {
+        config.output_format = args.output_format;
+    }
+    if let Some(vsync) = args.vsync {
+        config.vsync = matches!(vsync, Vsync::On);
+    }
+    if let Some(scale) = args.render_scale {
+        config.render_scale = scale;
+    }
+    if args.frame_cap.is_some() {
+        config.frame_cap = args.frame_cap;
+    }
+    if args.scale_factor.is_some() {
+        config.scale_factor = args.scale_factor;
+    }
+}
+
+// only errors by default, like env_logger on its own
+fn log_level(verbose: u8) -> LevelFilter {
+    match verbose {
+        0 => LevelFilter::Error,
+        1 => LevelFilter::Warn,
+        2 => LevelFilter::Info,
+        3 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+// goes to stdout rather than the log, it's the whole point of running with --list-adapters
+fn list_adapters(backends: wgpu::BackendBit) {
+    let instance = wgpu::Instance::new(backends);
+    let adapters = enumerate_adapters(&instance, backends);
+    if adapters.is_empty() {
+        println!("no adapters found on {:?}", backends);
+        return;
+    }
+
+    let name_width = adapters.iter().map(|info| info.name.len()).max().unwrap_or(0).max("name".len());
+    println!("{:<5}  {:<name_width$}  {:<7}  type", "index", "name", "backend", name_width = name_width);
+    for (index, info) in adapters.iter().enumerate() {
+        println!("{:<5}  {:<name_width$}  {:<7}  {:?}",
+            index, info.name, format!("{:?}", info.backend), info.device_type, name_width = name_width);
+    }
+}
+
+// the parts of the config that live in imgui rather than the renderer
+fn apply_ui_config(config: &Config, imstate: &mut ImguiState, renderer: &Renderer) {
+    if let Some(theme) = config.theme {
+        imstate.set_theme(theme);
+    }
+    if let Some(font) = &config.font {
+        if let Err(e) = imstate.set_default_ttf_font(renderer.device(), renderer.queue(), font, config.font_size) {
+            error!("{:?}", e);
+        }
+    }
+    for (name, path) in &config.fonts {
+        if let Err(e) = imstate.add_named_font(renderer.device(), renderer.queue(), name, path, config.font_size) {
+            error!("{:?}", e);
+        }
+    }
+}
+
+// the background color is saved as "r g b" next to the imgui layout
+fn load_clear_color(path: &Path) -> Option<[f32; 3]> {
+    let saved = std::fs::read_to_string(path).ok()?;
+    let rgb: Vec<f32> = saved.split_whitespace().map(str::parse).collect::<Result<_, _>>().ok()?;
+    match rgb[..] {
+        [r, g, b] => Some([r, g, b]),
+        _ => None,
+    }
+}
+
+// the picker (and the saved and configured colors) are sRGB, which is what people expect from one
+fn picked_color([r, g, b]: [f32; 3]) -> wgpu::Color {
+    srgb_to_linear(wgpu::Color { r: r as f64, g: g as f64, b: b as f64, a: 1.0 })
+}
+
+// where the window was and how big, saved next to the imgui layout too. the position is missing
+// where the platform won't say (wayland). whether the debug overlay was open rides along, since
+// it's ui state rather than something the user set in the config
+#[derive(Serialize, Deserialize)]
+struct WindowGeometry {
+    position: Option<PhysicalPosition<i32>>,
     size: PhysicalSize<u32>,
-    last_frame_ts: Instant,
-    last_cursor: Option<Option<imgui::MouseCursor>>,
+    #[serde(default)]
+    debug_overlay: bool,
 }
 
-#[derive(Error, Debug)]
-enum RenderError {
-    #[error("getting a swapchain frame failed")]
-    SwapChainError(#[from] wgpu::SwapChainError),
+fn load_window_geometry(path: &Path) -> Option<WindowGeometry> {
+    let saved = std::fs::read_to_string(path).ok()?;
+    toml::from_str(&saved).map_err(|e| warn!("ignoring the saved window geometry in {}: {}", path.display(), e)).ok()
+}
 
-    #[error("error preparing imgui frame")]
-    ImguiFramePrepError { source: winit::error::ExternalError },
+fn save_window_geometry(path: &Path, window: &Window, debug_overlay: bool) {
+    // minimized windows are 0x0, and there's no point coming back at whatever size fullscreen was,
+    // so those keep last time's
+    let size = window.inner_size();
+    let geometry = if size.width == 0 || size.height == 0 || window.fullscreen().is_some() {
+        match load_window_geometry(path) {
+            Some(saved) => WindowGeometry { debug_overlay, ..saved },
+            None => return,
+        }
+    } else {
+        WindowGeometry { position: window.outer_position().ok(), size, debug_overlay }
+    };
+    let saved = toml::to_string(&geometry).map_err(anyhow::Error::new)
+        .and_then(|text| std::fs::write(path, text).map_err(anyhow::Error::new));
+    if let Err(e) = saved {
+        warn!("couldn't save the window geometry to {}: {}", path.display(), e);
+    }
+}
 
-    #[error("failed to render imgui frame")]
-    ImguiRendererError(imgui_wgpu::RendererError),
+// puts the window back where it was, as long as that's still on a monitor; otherwise (say it was
+// on one that's been unplugged) it goes in the middle of the primary one
+fn restore_window_position(window: &Window, saved: PhysicalPosition<i32>) {
+    let size = window.outer_size();
+    let on_monitor = window.available_monitors().find(|monitor| {
+        let (origin, extent) = (monitor.position(), monitor.size());
+        (origin.x..origin.x + extent.width as i32).contains(&saved.x) && (origin.y..origin.y + extent.height as i32).contains(&saved.y)
+    });
+
+    let position = match on_monitor {
+        // the top left corner being on it isn't enough if the rest hangs off the edge
+        Some(monitor) => {
+            let (origin, extent) = (monitor.position(), monitor.size());
+            let clamp = |at: i32, start: i32, monitor_len: u32, window_len: u32| {
+                at.min(start + monitor_len as i32 - window_len as i32).max(start)
+            };
+            PhysicalPosition::new(
+                clamp(saved.x, origin.x, extent.width, size.width),
+                clamp(saved.y, origin.y, extent.height, size.height))
+        },
+        None => {
+            info!("the window's saved position isn't on any monitor, centering it");
+            let monitor = window.primary_monitor();
+            let (origin, extent) = (monitor.position(), monitor.size());
+            PhysicalPosition::new(
+                origin.x + (extent.width as i32 - size.width as i32) / 2,
+                origin.y + (extent.height as i32 - size.height as i32) / 2)
+        },
+    };
+    window.set_outer_position(position);
 }
 
-impl Renderer {
-    async fn new(window: &Window) -> Result<Self> {
-        let size = window.inner_size();
+fn save_clear_color(path: &Path, [r, g, b]: [f32; 3]) {
+    if let Err(e) = std::fs::write(path, format!("{} {} {}", r, g, b)) {
+        warn!("couldn't save the background color to {}: {}", path.display(), e);
+    }
+}
 
-        // PRIMARY => VK, Metal, DX12, BWebGpu
-        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+// the window's size changes with its decorations, which comes back through WindowEvent::Resized.
+// in fullscreen there's no title bar either way, so it waits until F11 takes us out again
+fn apply_decorations(window: &Window, fullscreen: bool, decorations: bool) {
+    if !fullscreen {
+        window.set_decorations(decorations);
+    }
+}
 
-        let surface = unsafe { instance.create_surface(window) };
+fn load_icon(path: &Path) -> Result<Icon> {
+    let image = image::open(path)
+        .with_context(|| format!("couldn't load {}", path.display()))?
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    Icon::from_rgba(image.into_raw(), width, height)
+        .with_context(|| format!("{} isn't usable as an icon", path.display()))
+}
 
-        // adapter just identifies the device we want to talk to
-        let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: Some(&surface),
-        }).await.ok_or(anyhow!("couldn't find an adapter!"))?;
+// one window and everything that draws into it. every window's renderer shares the first one's device
+struct AppWindow {
+    renderer: Renderer,
+    // None with --no-ui
+    imstate: Option<ImguiState>,
+    app: DemoApp,
+    // loaded again after the device is lost
+    image: Option<PathBuf>,
+    // only the first window saves its background color, geometry (or its imgui layout)
+    clear_color_path: Option<PathBuf>,
+    geometry_path: Option<PathBuf>,
+    swapchain_failures: u32,
+    device_recoveries: u32,
+    last_title_update: Instant,
+    fullscreen: bool,
+    decorations: bool,
+    always_on_top: bool,
+    // frames left to draw before there's nothing new to show, reset by any window event
+    redraws_pending: u32,
+    // dragging with the left button orbits the camera, as long as the drag didn't start on imgui
+    orbiting: bool,
+    last_cursor_pos: Option<PhysicalPosition<f64>>,
+    // where the pixel picker was clicked, read back after the next frame
+    pick_at: Option<PhysicalPosition<f64>>,
+    // held down right now, so shortcuts can ask for ctrl and so on
+    modifiers: ModifiersState,
+    // the newest size the window has been given and when, until it's held still for RESIZE_DEBOUNCE
+    pending_resize: Option<(PhysicalSize<u32>, Instant)>,
+    // false until the first frame, when the window gets shown in case it was built hidden
+    shown: bool,
+    // set when there was nothing to draw, so the next frame doesn't count the wait as its time
+    idle: bool,
+    // for rebuilding the renderer, from the config
+    init_timeout_secs: u64,
+    // fields drop in order, and the surface shouldn't outlive its window
+    window: Window,
+}
 
-        // and the device is an open connection to it
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
-                shader_validation: true,
-            },
-            None
-        ).await?;
+// a renderer stuck inside the driver isn't coming back, and neither is anything waiting on it
+fn exit_stuck(message: &str) {
+    error!("{}", message);
+    std::process::exit(1);
+}
+
+fn build_window(target: &EventLoopWindowTarget<()>, config: &Config, icon: Option<Icon>, transparent: bool) -> Result<Window> {
+    Ok(WindowBuilder::new()
+        .with_title(&config.title)
+        .with_inner_size(PhysicalSize::new(config.width, config.height))
+        .with_min_inner_size(PhysicalSize::new(MIN_WINDOW_SIZE.0, MIN_WINDOW_SIZE.1))
+        .with_window_icon(icon)
+        .with_visible(!config.hide_until_drawn)
+        .with_transparent(transparent)
+        .build(target)?)
+}
+
+fn configure_renderer(renderer: &mut Renderer, config: &Config) {
+    renderer.set_frame_cap(config.frame_cap);
+    renderer.set_scale_override(config.scale_factor);
+    if config.render_scale != 1.0 {
+        renderer.set_render_scale(config.render_scale);
+    }
+    if config.sample_count != 1 {
+        if let Err(e) = renderer.set_sample_count(config.sample_count) {
+            warn!("{:?}", e);
+        }
+    }
+}
 
-        let swapchain_desc = wgpu::SwapChainDescriptor {
-            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+// reads the config again and applies it to every window, logging what changed. flags given on the
+// command line still win
+fn reload_settings(path: Option<&Path>, args: &Args, config: &mut Config, windows: &mut HashMap<WindowId, AppWindow>) {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            warn!("no config file to reload");
+            return;
+        },
+    };
+    let mut fresh = match Config::load_or_create(path) {
+        Ok(fresh) => fresh,
+        Err(e) => {
+            error!("not reloading the config: {:?}", e);
+            return;
+        },
+    };
+    apply_args(&mut fresh, args);
+
+    macro_rules! log_changes {
+        ($($field:ident),*) => {
+            $(if config.$field != fresh.$field {
+                info!("{} changed from {:?} to {:?}", stringify!($field), config.$field, fresh.$field);
+            })*
         };
-        let swapchain = device.create_swap_chain(&surface, &swapchain_desc);
-
-        let last_frame_ts = Instant::now();
-
-        Ok(Renderer {
-            instance,
-            surface,
-            adapter,
-            device,
-            queue,
-            swapchain_desc,
-            swapchain,
-            size,
-            last_frame_ts,
-            last_cursor: None,
-        })
-    }
-
-    fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        debug!("resizing to {:?}", new_size);
-        self.size = new_size;
-        self.swapchain_desc.width = new_size.width;
-        self.swapchain_desc.height = new_size.height;
-        self.swapchain = self.device.create_swap_chain(&self.surface, &self.swapchain_desc);
-    }
-
-    fn render(&mut self, window: &Window, imstate: &mut ImguiState) -> Result<(), RenderError> {
-        let now = Instant::now();
-        let delta_t = now - self.last_frame_ts;
-        let frame = self.swapchain.get_current_frame()?;
-
-        imstate.ctx.io_mut().update_delta_time(delta_t);
-        self.last_frame_ts = now;
-
-        imstate.platform.prepare_frame(imstate.ctx.io_mut(), window).map_err(|e| RenderError::ImguiFramePrepError { source: e })?;
-
-        let ui = imstate.ctx.frame();
-
-        {
-            let window = imgui::Window::new(im_str!("Hello world"));
-            window
-                .size([300.0, 100.0], imgui::Condition::FirstUseEver)
-                .build(&ui, || {
-                    ui.text(im_str!("Hello world!"));
-                    ui.text(im_str!("This...is...imgui-rs on WGPU!"));
-                    ui.separator();
-                    let mouse_pos = ui.io().mouse_pos;
-                    ui.text(im_str!(
-                        "Mouse Position: ({:.1},{:.1})",
-                        mouse_pos[0],
-                        mouse_pos[1]
-                    ));
-                });
+    }
+    log_changes!(title, clear_color, vsync, theme, frame_cap, render_scale, scale_factor, sample_count, font, font_size, fonts, keys);
+    // these are only looked at when the renderer or window is made
+    if config.backend != fresh.backend || config.output_format != fresh.output_format {
+        warn!("the backend and output format only change after a restart");
+    }
+
+    for state in windows.values_mut() {
+        state.apply_config(config, &fresh);
+    }
+    *config = fresh;
+    info!("reloaded {}", path.display());
+}
+
+impl AppWindow {
+    fn new(window: Window, renderer: Renderer, imstate: Option<ImguiState>, mut app: DemoApp) -> Self {
+        if let Some(imstate) = &imstate {
+            app.theme = imstate.theme();
+            app.fonts = imstate.named_fonts();
+        }
+        AppWindow {
+            renderer,
+            imstate,
+            app,
+            image: None,
+            clear_color_path: None,
+            geometry_path: None,
+            swapchain_failures: 0,
+            device_recoveries: 0,
+            last_title_update: Instant::now(),
+            fullscreen: false,
+            decorations: true,
+            always_on_top: false,
+            redraws_pending: SETTLE_FRAMES,
+            orbiting: false,
+            last_cursor_pos: None,
+            pick_at: None,
+            modifiers: ModifiersState::empty(),
+            pending_resize: None,
+            shown: false,
+            idle: false,
+            init_timeout_secs: 10,
+            window,
+        }
+    }
+
+    // another window showing the demo, drawing with this one's device
+    fn open_another(&self, target: &EventLoopWindowTarget<()>, config: &Config, icon: Option<Icon>, app: DemoApp) -> Result<Self> {
+        let window = build_window(target, config, icon, self.renderer.is_transparent())?;
+        let mut renderer = self.renderer.for_window(&window)?;
+        configure_renderer(&mut renderer, config);
+
+        // two contexts saving to the same layout file would just overwrite each other
+        let imstate = self.imstate.as_ref().map(|_| {
+            let mut imstate = ImguiState::new(&window, &renderer, None);
+            apply_ui_config(config, &mut imstate, &renderer);
+            imstate
+        });
+
+        let mut opened = AppWindow::new(window, renderer, imstate, app);
+        opened.app.clear_color = self.app.clear_color;
+        opened.app.show_debug_overlay = self.app.show_debug_overlay;
+        opened.init_timeout_secs = config.init_timeout_secs;
+        Ok(opened)
+    }
+
+    // returns whether the window should stay open
+    fn handle_window_event(&mut self, event: &mut WindowEvent) -> bool {
+        self.redraws_pending = SETTLE_FRAMES;
+
+        // as of the last frame; shortcuts and the camera leave alone whatever imgui is using
+        let (imgui_wants_mouse, imgui_wants_keyboard) = self.imstate.as_ref().map_or((false, false), ImguiState::wants_input);
+        let renderer = &mut self.renderer;
+
+        // the one event that can be answered, by picking the window's new size. it's a one off,
+        // unlike dragging an edge, so there's nothing to wait out
+        if let WindowEvent::ScaleFactorChanged { new_inner_size, .. } = event {
+            **new_inner_size = renderer.resize_to_scale(&self.window, **new_inner_size);
+            self.pending_resize = None;
+            return true;
+        }
+
+        match &*event {
+            WindowEvent::CloseRequested => return false,
+
+            WindowEvent::Focused(focused) => {
+                renderer.set_focused(*focused);
+                // releases while we're in the background go to some other window
+                if !focused {
+                    self.modifiers = ModifiersState::empty();
+                }
+            },
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = *modifiers,
+
+            WindowEvent::HoveredFile(path) => self.app.hovered_file = Some(path.clone()),
+            WindowEvent::HoveredFileCancelled => self.app.hovered_file = None,
+            WindowEvent::DroppedFile(path) => {
+                self.app.hovered_file = None;
+                if image::ImageFormat::from_path(path).is_err() {
+                    warn!("ignoring {}, it doesn't look like an image", path.display());
+                } else if let Some(imstate) = &mut self.imstate {
+                    load_image(imstate, renderer, path, &mut self.app);
+                } else {
+                    warn!("ignoring {}, there's no ui to show it in", path.display());
+                }
+            },
+
+            WindowEvent::Resized(size) => self.pending_resize = Some((*size, Instant::now())),
+
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. },
+                ..
+            } if !imgui_wants_keyboard => {
+                if let Some(action) = self.app.key_bindings.action(*key, self.modifiers) {
+                    self.perform(action);
+                }
+            },
+
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                let pressed = *state == ElementState::Pressed && !imgui_wants_mouse;
+                // with the picker open a click only picks, so the camera can't move under it
+                if pressed && self.app.show_picker {
+                    self.pick_at = self.last_cursor_pos;
+                }
+                self.orbiting = pressed && !self.app.show_picker;
+                renderer.set_cursor_override(if self.orbiting { Some(CursorIcon::Grabbing) } else { None });
+            },
 
-            let window = imgui::Window::new(im_str!("Hello too"));
-            window
-                .size([400.0, 200.0], imgui::Condition::FirstUseEver)
-                .position([400.0, 200.0], imgui::Condition::FirstUseEver)
-                .build(&ui, || {
-                    ui.text(im_str!("Frametime: {:?}", delta_t));
+            WindowEvent::CursorMoved { position, .. } => {
+                if let (true, Some(last)) = (self.orbiting, self.last_cursor_pos) {
+                    renderer.camera_mut().orbit((position.x - last.x) as f32, (position.y - last.y) as f32);
+                }
+                self.last_cursor_pos = Some(*position);
+            },
+            WindowEvent::CursorLeft { .. } => self.last_cursor_pos = None,
+
+            WindowEvent::MouseWheel { delta, .. } if !imgui_wants_mouse => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // roughly what a line is on most platforms
+                    MouseScrollDelta::PixelDelta(position) => (position.y / 20.0) as f32,
+                };
+                renderer.camera_mut().zoom(lines);
+            },
+
+            _ => ()
+        }
+
+        true
+    }
+
+    fn perform(&mut self, action: Action) {
+        let renderer = &mut self.renderer;
+        match action {
+            Action::CyclePresentMode => {
+                // cycle through present modes so vsync on/off can be compared live
+                let next = match renderer.present_mode() {
+                    wgpu::PresentMode::Fifo => wgpu::PresentMode::Mailbox,
+                    wgpu::PresentMode::Mailbox => wgpu::PresentMode::Immediate,
+                    wgpu::PresentMode::Immediate => wgpu::PresentMode::Fifo,
+                };
+                renderer.set_present_mode(next);
+                info!("present mode is now {:?}", renderer.present_mode());
+            },
+            Action::ToggleFullscreen => {
+                // the resize this causes comes back through WindowEvent::Resized
+                self.fullscreen = !self.fullscreen;
+                self.window.set_fullscreen(if self.fullscreen {
+                    Some(Fullscreen::Borderless(self.window.current_monitor()))
+                } else {
+                    None
                 });
+                // some window managers put the title bar back on the way out of fullscreen
+                apply_decorations(&self.window, self.fullscreen, self.decorations);
+            },
+            Action::ToggleDecorations => {
+                self.decorations = !self.decorations;
+                apply_decorations(&self.window, self.fullscreen, self.decorations);
+            },
+            Action::ToggleAlwaysOnTop => {
+                // independent of fullscreen and decorations, so it can just be set
+                self.always_on_top = !self.always_on_top;
+                self.window.set_always_on_top(self.always_on_top);
+            },
+            Action::Screenshot => self.screenshot(),
+            Action::Pause => {
+                renderer.set_paused(!renderer.is_paused());
+                info!("{}", if renderer.is_paused() { "paused" } else { "resumed" });
+            },
+            Action::Step if renderer.is_paused() => renderer.step(),
+            Action::Step => (),
+            Action::ReloadConfig => self.app.reload_config.set(true),
+            Action::ToggleDebugOverlay => self.app.show_debug_overlay = !self.app.show_debug_overlay,
+            Action::Quit => self.app.quit.set(true),
+        }
+    }
 
-            ui.show_demo_window(&mut true);
+    // whatever differs between `old` and `new` that can change while running
+    fn apply_config(&mut self, old: &Config, new: &Config) {
+        let renderer = &mut self.renderer;
+        if let (true, Some(color)) = (old.clear_color != new.clear_color, new.clear_color) {
+            self.app.clear_color = color;
+        }
+        if old.vsync != new.vsync {
+            renderer.set_present_mode(if new.vsync { wgpu::PresentMode::Fifo } else { wgpu::PresentMode::Immediate });
+        }
+        if let (true, Some(theme)) = (old.theme != new.theme, new.theme) {
+            self.app.theme = theme;
         }
+        renderer.set_frame_cap(new.frame_cap);
+        if old.render_scale != new.render_scale {
+            renderer.set_render_scale(new.render_scale);
+        }
+        renderer.set_scale_override(new.scale_factor);
+        if old.sample_count != new.sample_count {
+            // rebuilds the pipelines and render targets
+            if let Err(e) = renderer.set_sample_count(new.sample_count) {
+                warn!("{:?}", e);
+            }
+        }
+        if let (true, Some(imstate)) = (old.font != new.font || old.font_size != new.font_size || old.fonts != new.fonts, &mut self.imstate) {
+            apply_ui_config(new, imstate, renderer);
+            self.app.fonts = imstate.named_fonts();
+        }
+        self.app.key_bindings = new.keys.clone();
+        self.init_timeout_secs = new.init_timeout_secs;
+        self.redraws_pending = SETTLE_FRAMES;
+    }
 
-        // update mouse cursor if we need to
-        if self.last_cursor != Some(ui.mouse_cursor()) {
-            self.last_cursor = Some(ui.mouse_cursor());
-            imstate.platform.prepare_render(&ui, &window);
+    fn screenshot(&mut self) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let path = format!("screenshot-{}.png", timestamp);
+        if let Err(e) = self.renderer.capture_frame(Path::new(&path)) {
+            error!("{:?}", e);
         }
+    }
 
-        // used to encode series of gpu operations!
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("render encoder"),
-        });
+    fn apply_pending_resize(&mut self) {
+        if let Some((size, _)) = self.pending_resize.take() {
+            self.renderer.resize(size);
+            self.redraws_pending = SETTLE_FRAMES;
+        }
+    }
 
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                color_attachments: &[
-                    wgpu::RenderPassColorAttachmentDescriptor {
-                        attachment: &frame.output.view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color {
-                                r: 0.1,
-                                g: 0.2,
-                                b: 0.3,
-                                a: 1.0,
-                            }),
-                            store: true,
-                        }
+    // between batches of events; returns when this window next needs to wake the loop up,
+    // or None if it wants to draw as soon as it can
+    fn update(&mut self, title: &str) -> Option<Instant> {
+        let resize_due = match self.pending_resize {
+            Some((_, resized_at)) if resized_at.elapsed() < RESIZE_DEBOUNCE => Some(resized_at + RESIZE_DEBOUNCE),
+            _ => {
+                self.apply_pending_resize();
+                None
+            },
+        };
+
+        match (self.next_wake(title), resize_due) {
+            (Some(wake), Some(resize_due)) => Some(wake.min(resize_due)),
+            (wake, _) => wake,
+        }
+    }
+
+    fn next_wake(&mut self, title: &str) -> Option<Instant> {
+        if self.last_title_update.elapsed() >= TITLE_UPDATE_INTERVAL {
+            self.window.set_title(&format!("{} — {:.0} fps ({:.1} ms)",
+                title,
+                self.renderer.current_fps(),
+                self.renderer.frame_time().as_secs_f64() * 1000.0));
+            self.last_title_update = Instant::now();
+        }
+
+        if self.renderer.reload_changed_shaders() {
+            self.redraws_pending = SETTLE_FRAMES;
+        }
+
+        // when idle, only wake up again to refresh the title
+        // a capture sequence wants consecutive frames, whether or not anything's changing, and the
+        // particles keep moving on their own until paused
+        let needs_redraw = self.redraws_pending > 0
+            || self.imstate.as_ref().is_some_and(ImguiState::has_pending_work)
+            || self.renderer.capture_sequence_progress().is_some()
+            || (self.renderer.show_particles() && !self.renderer.is_paused());
+        if !needs_redraw {
+            self.idle = true;
+            return Some(self.last_title_update + TITLE_UPDATE_INTERVAL);
+        }
+
+        // sleep until the next frame is due instead of spinning when there's a cap
+        match self.renderer.next_frame_deadline() {
+            Some(deadline) if Instant::now() < deadline => Some(deadline),
+            _ => {
+                self.window.request_redraw();
+                None
+            },
+        }
+    }
+
+    // returns whether the window can still be drawn to
+    fn redraw(&mut self) -> bool {
+        let (renderer, imstate, app) = (&mut self.renderer, &mut self.imstate, &mut self.app);
+
+        self.redraws_pending = self.redraws_pending.saturating_sub(1);
+        if std::mem::take(&mut self.idle) {
+            renderer.restart_frame_timer();
+        }
+        app.paused = renderer.is_paused();
+        app.gpu_error = renderer.last_error().map(str::to_owned);
+        app.decorations = self.decorations;
+        app.always_on_top = self.always_on_top;
+        app.present_mode = renderer.present_mode();
+        app.supported_present_modes = renderer.supported_present_modes();
+        app.tonemapping = renderer.tonemapping();
+        app.show_particles = renderer.show_particles();
+        app.wireframe = renderer.wireframe();
+        app.exposure = renderer.exposure();
+        if let Some(imstate) = imstate.as_mut() {
+            sync_ui_settings(renderer, imstate, app);
+        }
+        renderer.set_clear_color(picked_color(app.clear_color));
+        // App::ui doesn't get the renderer, so the overlay goes on top of it here
+        let result = renderer.begin_frame(&self.window, imstate.as_mut()).and_then(|frame| match frame {
+            Some(frame) => {
+                if let Some(ui) = frame.ui() {
+                    app.ui(ui, frame.delta());
+                    if app.show_debug_overlay {
+                        app.debug_overlay.draw(ui, renderer, &mut app.show_debug_overlay);
                     }
-                ],
-                depth_stencil_attachment: None,
-            });
+                }
+                renderer.end_frame(frame)
+            },
+            None => Ok(()),
+        });
+        if app.present_mode != renderer.present_mode() {
+            renderer.set_present_mode(app.present_mode);
+        }
+        if std::mem::take(&mut app.start_capture_sequence) {
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            let dir = format!("capture-{}", timestamp);
+            if let Err(e) = renderer.start_capture_sequence(Path::new(&dir), CAPTURE_SEQUENCE_FRAMES) {
+                error!("{:?}", e);
+            }
+        }
+        app.capture_progress = renderer.capture_sequence_progress();
+        if app.show_picker != renderer.keep_last_frame() {
+            renderer.set_keep_last_frame(app.show_picker);
+        }
+        if let Some(position) = self.pick_at.take() {
+            let (x, y) = (position.x as u32, position.y as u32);
+            match renderer.read_pixel(x, y) {
+                Ok(pixel) => app.picked_pixel = Some(([x, y], pixel)),
+                Err(e) => warn!("couldn't pick a pixel: {:?}", e),
+            }
+        }
+        if app.wireframe != renderer.wireframe() {
+            renderer.set_wireframe(app.wireframe);
+        }
+        if app.show_particles != renderer.show_particles() {
+            renderer.set_show_particles(app.show_particles);
+        }
+        if app.tonemapping != renderer.tonemapping() {
+            renderer.set_tonemapping(app.tonemapping);
+        }
+        if app.exposure != renderer.exposure() {
+            renderer.set_exposure(app.exposure);
+        }
+        if app.decorations != self.decorations {
+            self.decorations = app.decorations;
+            apply_decorations(&self.window, self.fullscreen, self.decorations);
+        }
+        if app.always_on_top != self.always_on_top {
+            self.always_on_top = app.always_on_top;
+            self.window.set_always_on_top(self.always_on_top);
+        }
+        if app.debug_overlay.take_reset_request() {
+            renderer.reset_frame_stats();
+        }
+        if std::mem::take(&mut app.dismiss_gpu_error) {
+            renderer.clear_last_error();
+        }
+        if let (true, Some(imstate)) = (app.show_depth && (std::mem::take(&mut app.refresh_depth) || app.live_depth), imstate.as_mut()) {
+            update_depth_image(renderer, imstate, app);
+        }
 
-            imstate.renderer.render(ui.render(), &self.queue, &self.device, &mut rpass).map_err(RenderError::ImguiRendererError)
-        }?;
+        match result {
+            Ok(_) => {
+                self.swapchain_failures = 0;
+                self.device_recoveries = 0;
+                if !self.shown {
+                    self.window.set_visible(true);
+                    self.shown = true;
+                }
+            },
+            Err(RenderError::SwapChainError(e @ wgpu::SwapChainError::Lost))
+            | Err(RenderError::SwapChainError(e @ wgpu::SwapChainError::Outdated)) => {
+                self.swapchain_failures += 1;
+                if self.swapchain_failures > MAX_SWAPCHAIN_RECREATIONS && self.device_recoveries >= MAX_DEVICE_RECOVERIES {
+                    error!("swapchain still {:?} after rebuilding the renderer, giving up", e);
+                    return false;
+                } else if self.swapchain_failures > MAX_SWAPCHAIN_RECREATIONS {
+                    // the swapchain alone not coming back usually means the device is gone
+                    self.device_recoveries += 1;
+                    self.swapchain_failures = 0;
+                    let timeout = Duration::from_secs(self.init_timeout_secs);
+                    match block_on_timeout_or(renderer.recover(&self.window), timeout, "rebuilding the renderer", exit_stuck).and_then(|result| result) {
+                        Ok(()) => {
+                            app.images.clear();
+                            app.depth_image = None;
+                            if let Some(imstate) = imstate.as_mut() {
+                                imstate.recover(renderer);
+                                if let Some(path) = &self.image {
+                                    load_image(imstate, renderer, path, app);
+                                }
+                            }
+                        },
+                        Err(e) => {
+                            error!("couldn't rebuild the renderer: {:?}", e);
+                            return false;
+                        },
+                    }
+                } else if self.pending_resize.is_some() {
+                    // the old size is what made it outdated, so don't wait to catch up
+                    self.apply_pending_resize();
+                } else {
+                    renderer.resize(renderer.size());
+                }
+            },
+            Err(e) if e.is_fatal() => {
+                error!("{:?}", anyhow::Error::new(e));
+                return false;
+            },
+            Err(e) => warn!("dropped a frame: {:?}", anyhow::Error::new(e)),
+        }
 
+        true
+    }
+}
 
-        self.queue.submit(std::iter::once(encoder.finish()));
+impl AppWindow {
+    // saves whatever this window saves and shuts its renderer down, in the order that leaves the
+    // device with nothing in flight
+    fn close(self) {
+        if let Some(path) = &self.clear_color_path {
+            save_clear_color(path, self.app.clear_color);
+        }
+        if let Some(path) = &self.geometry_path {
+            save_window_geometry(path, &self.window, self.app.show_debug_overlay);
+        }
 
-        Ok(())
+        let AppWindow { renderer, mut imstate, app, window, .. } = self;
+        renderer.wait_for_gpu_idle();
+        if let Some(imstate) = &mut imstate {
+            imstate.free_textures();
+        }
+        drop(app);
+        drop(imstate);
+        renderer.shutdown();
+        drop(window);
     }
 }
 
-struct ImguiState {
-    ctx: imgui::Context,
-    platform: imgui_winit_support::WinitPlatform,
-    renderer: imgui_wgpu::Renderer,
+// renders `frames` frames offscreen as fast as it can and prints how long they took
+fn run_headless(args: &Args, config: &Config, renderer_config: &RendererConfig, console: LogConsole, frames: u32) -> Result<()> {
+    let timeout = Duration::from_secs(config.init_timeout_secs);
+    let mut renderer = block_on_timeout_or(Renderer::new_headless_with_config(config.width, config.height, renderer_config), timeout, "creating the renderer", exit_stuck)??;
+    if config.render_scale != 1.0 {
+        renderer.set_render_scale(config.render_scale);
+    }
+    renderer.set_time_source(TimeSource::Fixed { step: HEADLESS_FRAME_STEP });
+    if let Some(color) = config.clear_color {
+        renderer.set_clear_color(picked_color(color));
+    }
+
+    let mut imstate = (!args.no_ui).then(|| {
+        let mut imstate = ImguiState::new_headless(&renderer);
+        apply_ui_config(config, &mut imstate, &renderer);
+        imstate
+    });
+    let mut app = DemoApp::new(console, Rc::new(Cell::new(false)), Rc::new(Cell::new(false)), Rc::new(Cell::new(false)), config.keys.clone());
+    if let (Some(path), Some(imstate)) = (&args.image, &mut imstate) {
+        load_image(imstate, &renderer, path, &mut app);
+    }
+
+    // so the json covers every frame, and not loading everything above
+    renderer.set_frame_stats_window(frames as usize);
+    let mut frame_times = Vec::with_capacity(frames as usize);
+    for _ in 0..frames {
+        let start = Instant::now();
+        renderer.render_headless(imstate.as_mut(), &mut app)?;
+        // otherwise this only measures how fast work gets queued
+        renderer.poll(wgpu::Maintain::Wait);
+        frame_times.push(start.elapsed());
+    }
+
+    let total: Duration = frame_times.iter().sum();
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    println!("rendered {} frames at {}x{} in {:.1} ms: avg {:.2} ms, min {:.2} ms, max {:.2} ms",
+        frames,
+        config.width,
+        config.height,
+        ms(total),
+        ms(total / frames),
+        ms(frame_times.iter().copied().min().unwrap_or_default()),
+        ms(frame_times.iter().copied().max().unwrap_or_default()));
+    renderer.dump_stats_json(&args.stats_json)?;
+    info!("wrote frame stats to {}", args.stats_json.display());
+
+    if let Some(imstate) = &mut imstate {
+        imstate.free_textures();
+    }
+    drop(imstate);
+    renderer.shutdown();
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    env_logger::init();
+    let args = Args::parse();
+    let console = LogConsole::install(log_level(args.verbose))?;
 
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .build(&event_loop)?;
-
-    let mut renderer = block_on(Renderer::new(&window))?;
-
-    let mut imstate = {
-        let mut ctx = imgui::Context::create();
-        let mut platform = imgui_winit_support::WinitPlatform::init(&mut ctx);
-        platform.attach_window(ctx.io_mut(),
-            &window,
-            imgui_winit_support::HiDpiMode::Default);
-        ctx.set_ini_filename(None);
-    
-        let rend_config = imgui_wgpu::RendererConfig::new().set_texture_format(renderer.swapchain_desc.format);
-        let mut renderer = imgui_wgpu::Renderer::new(&mut ctx, &renderer.device, &renderer.queue, rend_config);
-
-        ImguiState { ctx, platform, renderer }
+    let config_path = args.config.clone().or_else(default_config_path);
+    let mut config = match &config_path {
+        Some(path) => Config::load_or_create(path)?,
+        None => {
+            warn!("no config directory on this platform, using the default settings");
+            Config::default()
+        },
     };
+    apply_args(&mut config, &args);
+
+    let renderer_config = RendererConfig {
+        backends: config.backend.into(),
+        adapter_index: args.adapter_index,
+        force_fallback: args.force_fallback,
+        trace_dir: args.trace_dir.clone(),
+        safe_mode: args.safe_mode,
+        transparent: args.transparent,
+        output_format: config.output_format.map(Into::into),
+        present_mode: if config.vsync { wgpu::PresentMode::Fifo } else { wgpu::PresentMode::Immediate },
+        ..Default::default()
+    };
+
+    if args.list_adapters {
+        list_adapters(renderer_config.backends);
+        return Ok(());
+    }
+
+    if let Some(frames) = args.headless_frames {
+        return run_headless(&args, &config, &renderer_config, console, frames);
+    }
+
+    let icon = args.icon.as_deref().and_then(|path| {
+        load_icon(path).map_err(|e| warn!("not setting a window icon: {:?}", e)).ok()
+    });
+
+    let layout_path = if args.no_persist_layout { None } else { default_layout_path() };
+    let geometry_path = layout_path.as_ref().map(|path| path.with_file_name("window.toml"));
+    let saved_geometry = geometry_path.as_deref().and_then(load_window_geometry);
+    // a size given on the command line beats last run's
+    if let (Some(saved), None, None) = (&saved_geometry, args.width, args.height) {
+        config.width = saved.size.width;
+        config.height = saved.size.height;
+    }
+
+    let event_loop = EventLoop::new();
+    let window = build_window(&event_loop, &config, icon.clone(), args.transparent)?;
+    if let Some(position) = saved_geometry.as_ref().and_then(|saved| saved.position) {
+        restore_window_position(&window, position);
+    }
+
+    let timeout = Duration::from_secs(config.init_timeout_secs);
+    let mut renderer = block_on_timeout_or(Renderer::with_config(&window, &renderer_config), timeout, "creating the renderer", exit_stuck)??;
+    configure_renderer(&mut renderer, &config);
+    if args.watch_shaders {
+        let shaders = Path::new(SHADER_DIR);
+        let watched = renderer.watch_triangle_shaders(shaders.join("triangle.vert.spv"), shaders.join("triangle.frag.spv"));
+        if let Err(e) = watched {
+            warn!("not hot reloading shaders: {:?}", e);
+        }
+    }
+
+    let clear_color_path = layout_path.as_ref().map(|path| path.with_file_name("clear_color"));
+    let mut imstate = (!args.no_ui).then(|| {
+        let mut imstate = ImguiState::new(&window, &renderer, layout_path);
+        apply_ui_config(&config, &mut imstate, &renderer);
+        imstate
+    });
 
+    let quit = Rc::new(Cell::new(false));
+    let new_window = Rc::new(Cell::new(false));
+    let reload_config = Rc::new(Cell::new(false));
+    let mut app = DemoApp::new(console.clone(), quit.clone(), new_window.clone(), reload_config.clone(), config.keys.clone());
+    app.show_debug_overlay = saved_geometry.as_ref().is_some_and(|saved| saved.debug_overlay);
+    let saved_clear_color = clear_color_path.as_deref().and_then(load_clear_color);
+    app.clear_color = config.clear_color.or(saved_clear_color).unwrap_or_else(|| {
+        let color = linear_to_srgb(renderer.clear_color());
+        [color.r as f32, color.g as f32, color.b as f32]
+    });
+
+    if let (Some(path), Some(imstate)) = (&args.image, &mut imstate) {
+        load_image(imstate, &renderer, path, &mut app);
+    }
 
-    let font_size = (13. * window.scale_factor()) as f32;
-    imstate.ctx.io_mut().font_global_scale = (1.0/window.scale_factor()) as f32;
+    let mut first = AppWindow::new(window, renderer, imstate, app);
+    first.image = args.image.clone();
+    first.clear_color_path = clear_color_path;
+    first.geometry_path = geometry_path;
+    first.init_timeout_secs = config.init_timeout_secs;
 
-    imstate.ctx.fonts().add_font(&[imgui::FontSource::DefaultFontData {
-        config: Some(imgui::FontConfig {
-            oversample_h: 1,
-            pixel_snap_h: true,
-            size_pixels: font_size,
-            ..Default::default()
-        }),
-    }]);
+    // get something on screen before the event loop gets going, rather than whatever garbage the
+    // window starts out with
+    if !first.redraw() {
+        return Err(anyhow!("couldn't draw the first frame"));
+    }
 
-    event_loop.run(move |event, _, control_flow| {
+    let mut windows = HashMap::new();
+    windows.insert(first.window.id(), first);
+
+    event_loop.run(move |mut event, target, control_flow| {
         match event {
-            Event::WindowEvent {ref event, window_id} if window_id == window.id() => match event {
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-
-                WindowEvent::Resized(size) => renderer.resize(*size),
-                WindowEvent::ScaleFactorChanged {new_inner_size, ..} => renderer.resize(**new_inner_size),
-
-                _ => ()
-            },
-            Event::MainEventsCleared => window.request_redraw(),
-            Event::RedrawRequested(window_id) if window_id == window.id() => {
-                match renderer.render(&window, &mut imstate) {
-                    Ok(_) => (),
-                    Err(RenderError::SwapChainError(e)) => match e {
-                        wgpu::SwapChainError::Lost => renderer.resize(renderer.size),
-                        wgpu::SwapChainError::OutOfMemory => {
-                            error!("swapchain error: out of memory");
-                            *control_flow = ControlFlow::Exit;
-                        },
-                        e => error!("swapchain error (not fatal, just framedrop): {:?}", e),
+            Event::WindowEvent {event: ref mut window_event, window_id} => {
+                let keep_open = match windows.get_mut(&window_id) {
+                    Some(state) => {
+                        // resizes and scale factor changes need to reach both
+                        let keep_open = state.handle_window_event(window_event);
+                        if let Some(imstate) = &mut state.imstate {
+                            imstate.handle_event(&state.window, &event);
+                        }
+                        keep_open
                     },
-                    r @ Err(_) => r.unwrap(),
+                    None => true,
+                };
+                if let (false, Some(state)) = (keep_open, windows.remove(&window_id)) {
+                    state.close();
+                }
+            },
+            Event::MainEventsCleared => {
+                if quit.get() || windows.is_empty() {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+
+                if reload_config.replace(false) {
+                    reload_settings(config_path.as_deref(), &args, &mut config, &mut windows);
+                }
+
+                if new_window.replace(false) {
+                    let app = DemoApp::new(console.clone(), quit.clone(), new_window.clone(), reload_config.clone(), config.keys.clone());
+                    // whichever window asked, they're all on the same device
+                    let opened = windows.values().next().map(|origin| origin.open_another(target, &config, icon.clone(), app));
+                    match opened {
+                        Some(Ok(opened)) => {
+                            windows.insert(opened.window.id(), opened);
+                        },
+                        Some(Err(e)) => error!("couldn't open another window: {:?}", e),
+                        None => (),
+                    }
+                }
+
+                // sleep until the soonest any window needs something, or not at all if one wants a frame now
+                *control_flow = ControlFlow::Wait;
+                for state in windows.values_mut() {
+                    *control_flow = match (*control_flow, state.update(&config.title)) {
+                        (ControlFlow::Poll, _) | (_, None) => ControlFlow::Poll,
+                        (ControlFlow::WaitUntil(soonest), Some(wake)) => ControlFlow::WaitUntil(soonest.min(wake)),
+                        (_, Some(wake)) => ControlFlow::WaitUntil(wake),
+                    };
+                }
+            },
+            Event::RedrawRequested(window_id) => {
+                let drawable = match windows.get_mut(&window_id) {
+                    Some(state) => state.redraw(),
+                    None => true,
+                };
+                if let (false, Some(state)) = (drawable, windows.remove(&window_id)) {
+                    state.close();
+                }
+            },
+            // imgui watches key releases device-wide so keys don't stick when focus moves mid-press
+            Event::DeviceEvent { .. } => {
+                for state in windows.values_mut() {
+                    if let Some(imstate) = &mut state.imstate {
+                        imstate.handle_event(&state.window, &event);
+                    }
+                }
+            },
+            Event::LoopDestroyed => {
+                // winit exits the process instead of returning, so this is the last chance to save anything
+                for (_, state) in windows.drain() {
+                    state.close();
                 }
             },
             _ => (),
         }
-
-        imstate.platform.handle_event(imstate.ctx.io_mut(), &window, &event);
     });
 }
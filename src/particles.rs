@@ -0,0 +1,99 @@
+use std::mem::size_of;
+use std::time::Duration;
+
+use crate::triangle::{TrianglePipeline, Vertex};
+
+const MAX_PARTICLES: usize = 512;
+// per second, and how many seconds each one lasts
+const SPAWN_RATE: f32 = 150.0;
+const LIFETIME: f32 = 2.5;
+// in world units per second (squared)
+const GRAVITY: f32 = -1.2;
+const LAUNCH_SPEED: f32 = 1.4;
+// half the width of each particle's triangle
+const PARTICLE_SIZE: f32 = 0.012;
+
+struct Particle {
+    position: [f32; 2],
+    velocity: [f32; 2],
+    age: f32,
+}
+
+// a fountain of little triangles simulated on the cpu, moved by however much time the frame says
+// passed and uploaded every frame, so it stops when paused and runs at the same speed at any fps
+pub(crate) struct Particles {
+    particles: Vec<Particle>,
+    // spawns owed from earlier frames, so low frame rates don't spawn fewer
+    to_spawn: f32,
+    rng: u32,
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+}
+
+impl Particles {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particle vertex buffer"),
+            size: (MAX_PARTICLES * 3 * size_of::<Vertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Particles { particles: Vec::with_capacity(MAX_PARTICLES), to_spawn: 0.0, rng: 0x9e37_79b9, vertex_buffer, vertex_count: 0 }
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, delta: Duration) {
+        let dt = delta.as_secs_f32();
+        for particle in &mut self.particles {
+            particle.age += dt;
+            particle.velocity[1] += GRAVITY * dt;
+            particle.position[0] += particle.velocity[0] * dt;
+            particle.position[1] += particle.velocity[1] * dt;
+        }
+        self.particles.retain(|particle| particle.age < LIFETIME);
+
+        self.to_spawn += SPAWN_RATE * dt;
+        while self.to_spawn >= 1.0 && self.particles.len() < MAX_PARTICLES {
+            self.to_spawn -= 1.0;
+            // up, give or take 20 degrees
+            let angle = std::f32::consts::FRAC_PI_2 + (self.random() - 0.5) * 0.7;
+            let speed = LAUNCH_SPEED * (0.7 + 0.3 * self.random());
+            self.particles.push(Particle {
+                position: [0.0, -0.9],
+                velocity: [angle.cos() * speed, angle.sin() * speed],
+                age: 0.0,
+            });
+        }
+        self.to_spawn = self.to_spawn.fract();
+
+        let vertices: Vec<Vertex> = self.particles.iter().flat_map(|particle| {
+            // yellow fading to red as they get older
+            let life = particle.age / LIFETIME;
+            let color = [1.0, 1.0 - life, 0.2 * (1.0 - life)];
+            let [x, y] = particle.position;
+            [
+                Vertex { position: [x, y + PARTICLE_SIZE, 0.0], color },
+                Vertex { position: [x - PARTICLE_SIZE, y - PARTICLE_SIZE, 0.0], color },
+                Vertex { position: [x + PARTICLE_SIZE, y - PARTICLE_SIZE, 0.0], color },
+            ]
+        }).collect();
+        if !vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        }
+        self.vertex_count = vertices.len() as u32;
+    }
+
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, pipeline: &'a TrianglePipeline, wireframe: bool) {
+        if self.vertex_count > 0 {
+            pipeline.draw_vertices(rpass, &self.vertex_buffer, self.vertex_count, wireframe);
+        }
+    }
+
+    // xorshift, in [0, 1); good enough for where a particle goes
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 8) as f32 / (1 << 24) as f32
+    }
+}
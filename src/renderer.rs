@@ -0,0 +1,1865 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use anyhow::{anyhow, Context, Result};
+use log::{debug, error, info, warn};
+use winit::{
+    window::{CursorIcon, Window},
+    dpi::PhysicalSize,
+};
+
+use crate::app::App;
+use crate::blit::BlitPipeline;
+use crate::camera::{Camera, CameraUniform};
+use crate::capture::{CaptureSequence, ReadbackPool};
+use crate::gui::{ImguiState, winit_cursor};
+use crate::particles::Particles;
+use crate::shader_watch::ShaderWatcher;
+use crate::stats::{FrameStats, StatsReport};
+use crate::texture::Texture;
+use crate::tonemap::TonemapPipeline;
+use crate::triangle::TrianglePipeline;
+
+// how many frames the fps counter averages over
+const FPS_WINDOW: usize = 30;
+// and how many the frame stats keep by default
+const FRAME_STATS_WINDOW: usize = 240;
+
+// longest delta the ui gets told about by default, so waking up after a long wait doesn't jump animations
+const DEFAULT_MAX_FRAME_DELTA: Duration = Duration::from_millis(100);
+
+const DEFAULT_UNFOCUSED_FRAME_CAP: u32 = 10;
+
+/// What the scene gets drawn in with tonemapping on, see `Renderer::set_tonemapping`.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const DEFAULT_EXPOSURE: f32 = 1.0;
+
+/// How the frame delta gets smoothed before imgui sees it, so its animations don't stutter on
+/// uneven frames. The app always gets the unsmoothed delta.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeltaSmoothing {
+    None,
+    /// Moves this fraction (0 to 1) of the way from the last smoothed delta to the new one each frame.
+    ExponentialMovingAverage(f32),
+    /// Lets the delta change by at most this fraction of the last one per frame.
+    Clamp(f32),
+}
+
+impl Default for DeltaSmoothing {
+    fn default() -> Self {
+        DeltaSmoothing::ExponentialMovingAverage(0.3)
+    }
+}
+
+impl DeltaSmoothing {
+    fn apply(self, previous: Duration, delta: Duration) -> Duration {
+        // nothing to smooth towards on the first frame
+        if previous == Duration::default() {
+            return delta;
+        }
+        match self {
+            DeltaSmoothing::None => delta,
+            DeltaSmoothing::ExponentialMovingAverage(weight) => {
+                let weight = weight.clamp(0.0, 1.0);
+                previous.mul_f32(1.0 - weight) + delta.mul_f32(weight)
+            },
+            DeltaSmoothing::Clamp(fraction) => {
+                let fraction = fraction.clamp(0.0, 1.0);
+                delta.clamp(previous.mul_f32(1.0 - fraction), previous.mul_f32(1.0 + fraction))
+            },
+        }
+    }
+}
+
+/// Where the frame delta comes from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeSource {
+    /// The time since the last frame, by the clock.
+    #[default]
+    Real,
+    /// Every frame advances by exactly `step`, however long it took, so animations come out the
+    /// same on any machine. Frame stats still measure real time.
+    Fixed { step: Duration },
+}
+
+/// A rectangle of the frame, in physical pixels from the top left.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    // the part of this that's inside `size`, if any
+    fn clamp_to(self, size: PhysicalSize<u32>) -> Option<Rect> {
+        let x = self.x.min(size.width);
+        let y = self.y.min(size.height);
+        let width = self.width.min(size.width - x);
+        let height = self.height.min(size.height - y);
+        if width == 0 || height == 0 {
+            None
+        } else {
+            Some(Rect { x, y, width, height })
+        }
+    }
+}
+
+// where finished frames go
+pub(crate) enum Output {
+    // the swapchain is only None while recovering from a lost device
+    Window { surface: wgpu::Surface, swapchain: Option<wgpu::SwapChain> },
+    // headless; usable as a copy source so frames can be read back
+    Texture { texture: Texture },
+}
+
+/// Extra drawing for `Renderer::set_pre_ui_pass`: gets the encoder for the frame, the frame's view, and the device and queue.
+pub type UserPass = Box<dyn FnMut(&mut wgpu::CommandEncoder, &wgpu::TextureView, &wgpu::Device, &wgpu::Queue)>;
+
+/// Called by `Renderer::resize` with the new size once everything's been recreated at it, and the device and queue.
+pub type ResizeCallback = Box<dyn FnMut(PhysicalSize<u32>, &wgpu::Device, &wgpu::Queue)>;
+
+pub struct Renderer {
+    // shared with any other renderers made with `for_window`
+    pub(crate) instance: Rc<wgpu::Instance>,
+    pub(crate) adapter: Rc<wgpu::Adapter>,
+    pub(crate) device: Rc<wgpu::Device>,
+    pub(crate) queue: Rc<wgpu::Queue>,
+    // describes the output texture whether or not there's an actual swapchain behind it
+    pub(crate) swapchain_desc: wgpu::SwapChainDescriptor,
+    pub(crate) output: Output,
+    // what we were created with, for rebuilding after a lost device
+    config: RendererConfig,
+    pub(crate) size: PhysicalSize<u32>,
+    pub(crate) depth: Texture,
+    sample_count: u32,
+    msaa_target: Option<Texture>,
+    render_scale: f32,
+    // where the scene goes when it's rendered below the output size
+    scaled_target: Option<(Texture, wgpu::BindGroup)>,
+    // what set_ui_viewport asked for, and the texture imgui gets drawn into for it, clamped to the frame
+    ui_viewport: Option<Rect>,
+    ui_target: Option<(Rect, Texture, wgpu::BindGroup)>,
+    blit: BlitPipeline,
+    // with tonemapping on the scene goes into hdr_target, which gets tonemapped (and stretched to
+    // the output if it's scaled) on its way to the frame
+    tonemapping: bool,
+    exposure: f32,
+    tonemap: TonemapPipeline,
+    hdr_target: Option<(Texture, wgpu::BindGroup)>,
+    // a RefCell so it can be called from encode_scene, which only gets &self
+    pre_ui_pass: RefCell<Option<UserPass>>,
+    on_resize: Option<ResizeCallback>,
+    triangle: TrianglePipeline,
+    // the triangle's SPIR-V on disk (vertex, fragment) and what's watching it, when hot reloading
+    triangle_shaders: Option<(ShaderWatcher, PathBuf, PathBuf)>,
+    show_triangle: bool,
+    // only around while they're being shown
+    particles: Option<Particles>,
+    wireframe: bool,
+    camera: Camera,
+    // written from `camera` at the start of every frame
+    camera_uniform: CameraUniform,
+    pub(crate) pending_capture: Option<PathBuf>,
+    pub(crate) capture_sequence: Option<CaptureSequence>,
+    pub(crate) readback_pool: ReadbackPool,
+    // with keep_last_frame on, window frames get drawn like captures and the texture kept for read_pixel
+    pub(crate) keep_last_frame: bool,
+    pub(crate) last_frame: Option<Texture>,
+    labels: FrameLabels,
+    clear_color: wgpu::Color,
+    // the last wgpu error we caught, until someone clears it
+    last_error: Option<String>,
+    is_minimized: bool,
+    last_frame_ts: Instant,
+    frame_cap: Option<u32>,
+    unfocused_frame_cap: Option<u32>,
+    focused: bool,
+    max_frame_delta: Duration,
+    delta_smoothing: DeltaSmoothing,
+    time_source: TimeSource,
+    // used for imgui instead of the window's scale factor when set
+    scale_override: Option<f64>,
+    // what imgui was last told, to smooth the next delta towards
+    smoothed_delta: Duration,
+    // while paused frames still get drawn, but with no time passing unless we're stepping
+    paused: bool,
+    step_requested: bool,
+    // the last few frame times for the fps counter, kept apart so the stats window doesn't change it
+    recent_frames: FrameStats,
+    frame_stats: FrameStats,
+    // set by restart_frame_timer, for the frame after it
+    skip_frame_stats: bool,
+    // what the os cursor was last set to, None being hidden
+    last_cursor: Option<Option<CursorIcon>>,
+    cursor_override: Option<CursorIcon>,
+}
+
+// what a frame's encoder and debug groups are called, with the debug prefix (whose frame it is, for
+// captures) in front. made once per prefix rather than formatted every frame
+struct FrameLabels {
+    encoder: String,
+    scene: String,
+    upscale: String,
+    tonemap: String,
+    imgui: String,
+    ui_viewport: String,
+    copy_capture: String,
+}
+
+impl FrameLabels {
+    fn new(prefix: &str) -> Self {
+        // render passes can't be labeled in this wgpu, so these also go on debug groups inside them
+        let label = |name: &str| if prefix.is_empty() { name.to_owned() } else { format!("{} {}", prefix, name) };
+        FrameLabels {
+            encoder: label("frame encoder"),
+            scene: label("scene"),
+            upscale: label("upscale"),
+            tonemap: label("tonemap"),
+            imgui: label("imgui"),
+            ui_viewport: label("ui viewport"),
+            copy_capture: label("copy capture to frame"),
+        }
+    }
+}
+
+/// A frame between `Renderer::begin_frame` and `Renderer::end_frame`.
+pub struct FrameContext<'a> {
+    // None when the frame was started without an ImguiState
+    imgui: Option<(imgui::Ui<'a>, &'a mut imgui_wgpu::Renderer)>,
+    encoder: wgpu::CommandEncoder,
+    target: FrameTarget,
+    capture_path: Option<PathBuf>,
+    delta: Duration,
+    smoothed_delta: Duration,
+    window: Option<&'a Window>,
+}
+
+// what a frame gets drawn into
+enum FrameTarget {
+    Swapchain(wgpu::SwapChainFrame),
+    // a headless renderer's output texture
+    Offscreen(wgpu::TextureView),
+    // a window frame that's being captured, which gets drawn into `texture` and blitted over at the end
+    Capture { frame: wgpu::SwapChainFrame, texture: Texture },
+}
+
+impl FrameTarget {
+    fn view(&self) -> &wgpu::TextureView {
+        match self {
+            FrameTarget::Swapchain(frame) => &frame.output.view,
+            FrameTarget::Offscreen(view) | FrameTarget::Capture { texture: Texture { view, .. }, .. } => view,
+        }
+    }
+}
+
+impl<'a> FrameContext<'a> {
+    /// For building this frame's imgui windows, if it has imgui.
+    pub fn ui(&self) -> Option<&imgui::Ui<'a>> {
+        self.imgui.as_ref().map(|(ui, _)| ui)
+    }
+
+    /// The frame delta, after clamping and pausing. This is what `App::ui` gets.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    /// `delta` after `Renderer::set_delta_smoothing`, which is what imgui got.
+    pub fn smoothed_delta(&self) -> Duration {
+        self.smoothed_delta
+    }
+
+    /// The frame's encoder, with the scene already recorded.
+    pub fn encoder(&mut self) -> &mut wgpu::CommandEncoder {
+        &mut self.encoder
+    }
+
+    /// What the frame is being drawn into: full resolution, single sampled, in the output format,
+    /// with the scene already in it. Passes drawing over it should use `LoadOp::Load`.
+    pub fn view(&self) -> &wgpu::TextureView {
+        self.target.view()
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Error, Debug)]
+pub enum RenderError {
+    #[error("getting a swapchain frame failed")]
+    SwapChainError(#[from] wgpu::SwapChainError),
+
+    #[error("error preparing imgui frame")]
+    ImguiFramePrepError { source: winit::error::ExternalError },
+
+    // imgui-wgpu's error doesn't implement Error, so it can't be a source
+    #[error("failed to render imgui frame: {0:?}")]
+    ImguiRendererError(imgui_wgpu::RendererError),
+
+    #[error("failed to capture frame")]
+    CaptureError(#[source] anyhow::Error),
+}
+
+impl RenderError {
+    /// Whether there's no point trying to render again; anything else just costs a frame.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, RenderError::SwapChainError(wgpu::SwapChainError::OutOfMemory))
+    }
+}
+
+pub fn enumerate_adapters(instance: &wgpu::Instance, backends: wgpu::BackendBit) -> Vec<wgpu::AdapterInfo> {
+    instance.enumerate_adapters(backends)
+        .map(|adapter| adapter.get_info())
+        .collect()
+}
+
+// wgpu 0.6 panics on invalid shaders and pipelines instead of returning an error, and has no
+// uncaptured error handler to hook. the panic happens after wgpu-core is done with its locks, so
+// carrying on afterwards is fine
+pub(crate) fn catch_wgpu_panic<T>(f: impl FnOnce() -> T) -> std::result::Result<T, String> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).map_err(|e| {
+        e.downcast_ref::<String>().cloned()
+            .or_else(|| e.downcast_ref::<&str>().map(|reason| reason.to_string()))
+            .unwrap_or_else(|| "unknown error".to_owned())
+    })
+}
+
+/// Converts a color from sRGB, which is what color pickers and hex codes give you, to the linear
+/// values `Renderer::set_clear_color` takes. Alpha is already linear and left alone.
+pub fn srgb_to_linear(color: wgpu::Color) -> wgpu::Color {
+    let channel = |c: f64| if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+    wgpu::Color { r: channel(color.r), g: channel(color.g), b: channel(color.b), a: color.a }
+}
+
+/// The inverse of `srgb_to_linear`, e.g. for showing `Renderer::clear_color` in a picker.
+pub fn linear_to_srgb(color: wgpu::Color) -> wgpu::Color {
+    let channel = |c: f64| if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    wgpu::Color { r: channel(color.r), g: channel(color.g), b: channel(color.b), a: color.a }
+}
+
+// wgpu doesn't let us ask the surface which present modes it supports, so this is a best guess
+// from what each backend's swapchain can do. fifo is the only one that's guaranteed everywhere.
+fn supported_present_modes(backend: wgpu::Backend) -> &'static [wgpu::PresentMode] {
+    use wgpu::PresentMode::*;
+
+    match backend {
+        wgpu::Backend::Vulkan => &[Fifo, Mailbox, Immediate],
+        wgpu::Backend::Dx12 | wgpu::Backend::Dx11 | wgpu::Backend::Metal => &[Fifo, Immediate],
+        _ => &[Fifo],
+    }
+}
+
+// wgpu can't tell us what the surface prefers yet (there's no get_swap_chain_preferred_format until
+// 0.7), but every swapchain backend it supports takes Bgra8UnormSrgb. offscreen we get to pick,
+// and rgba saves a swizzle when reading frames back
+fn default_output_format(has_surface: bool) -> wgpu::TextureFormat {
+    if has_surface {
+        wgpu::TextureFormat::Bgra8UnormSrgb
+    } else {
+        wgpu::TextureFormat::Rgba8UnormSrgb
+    }
+}
+
+// the 8 bit formats captures know how to read back, best first. metal layers only do bgra
+fn preferred_formats(backend: wgpu::Backend, has_surface: bool) -> &'static [wgpu::TextureFormat] {
+    use wgpu::TextureFormat::*;
+
+    match (backend, has_surface) {
+        (wgpu::Backend::Metal, true) => &[Bgra8UnormSrgb, Bgra8Unorm],
+        (_, true) => &[Bgra8UnormSrgb, Rgba8UnormSrgb, Bgra8Unorm, Rgba8Unorm],
+        (_, false) => &[Rgba8UnormSrgb, Bgra8UnormSrgb, Rgba8Unorm, Bgra8Unorm],
+    }
+}
+
+fn checked_output_format(backend: wgpu::Backend, has_surface: bool, format: Option<wgpu::TextureFormat>) -> wgpu::TextureFormat {
+    let default = default_output_format(has_surface);
+    match format {
+        Some(format) if !preferred_formats(backend, has_surface).contains(&format) => {
+            warn!("output format {:?} isn't supported here, falling back to {:?}", format, default);
+            default
+        },
+        Some(format) => format,
+        None => default,
+    }
+}
+
+fn checked_present_mode(backend: wgpu::Backend, mode: wgpu::PresentMode) -> wgpu::PresentMode {
+    if supported_present_modes(backend).contains(&mode) {
+        mode
+    } else {
+        warn!("present mode {:?} isn't supported here, falling back to Fifo", mode);
+        wgpu::PresentMode::Fifo
+    }
+}
+
+// wgpu can't tell us which multisample counts an adapter handles, so only allow the ones webgpu guarantees
+const SUPPORTED_SAMPLE_COUNTS: &[u32] = &[1, 4];
+
+fn create_output(device: &wgpu::Device, surface: Option<wgpu::Surface>, desc: &wgpu::SwapChainDescriptor) -> Output {
+    match surface {
+        Some(surface) => {
+            let swapchain = device.create_swap_chain(&surface, desc);
+            Output::Window { surface, swapchain: Some(swapchain) }
+        },
+        None => {
+            let texture = Texture::create(device, "offscreen output texture", PhysicalSize::new(desc.width, desc.height),
+                desc.format, 1, wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC);
+            Output::Texture { texture }
+        },
+    }
+}
+
+// names of the cpu rasterizers that don't report themselves as DeviceType::Cpu
+const SOFTWARE_ADAPTER_NAMES: [&str; 4] = ["llvmpipe", "lavapipe", "swiftshader", "microsoft basic render driver"];
+
+fn is_software_adapter(info: &wgpu::AdapterInfo) -> bool {
+    let name = info.name.to_lowercase();
+    info.device_type == wgpu::DeviceType::Cpu || SOFTWARE_ADAPTER_NAMES.iter().any(|software| name.contains(software))
+}
+
+// None when `backends` has no adapters at all (or no software ones, when that's what was asked
+// for), so the caller can try others
+async fn select_adapter(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface>,
+    backends: wgpu::BackendBit,
+    config: &RendererConfig,
+) -> Result<Option<wgpu::Adapter>> {
+    match config.adapter_index {
+        // wgpu doesn't have force_fallback_adapter yet, so go by what the adapters call themselves
+        None if config.force_fallback => Ok(instance.enumerate_adapters(backends).find(|adapter| is_software_adapter(&adapter.get_info()))),
+        Some(index) => {
+            let mut adapters: Vec<_> = instance.enumerate_adapters(backends).collect();
+            if adapters.is_empty() {
+                return Ok(None);
+            }
+            if index >= adapters.len() {
+                return Err(anyhow!("adapter index {} is out of range, only found {} adapters", index, adapters.len()));
+            }
+            Ok(Some(adapters.swap_remove(index)))
+        },
+        None => Ok(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: if config.safe_mode { wgpu::PowerPreference::LowPower } else { wgpu::PowerPreference::HighPerformance },
+            compatible_surface: surface,
+        }).await),
+    }
+}
+
+type Gpu = (wgpu::Instance, Option<wgpu::Surface>, wgpu::Adapter);
+
+async fn request_gpu_on(window: Option<&Window>, backends: wgpu::BackendBit, config: &RendererConfig) -> Result<Option<Gpu>> {
+    let instance = wgpu::Instance::new(backends);
+    let surface = window.map(|window| unsafe { instance.create_surface(window) });
+    // adapter just identifies the device we want to talk to
+    let adapter = select_adapter(&instance, surface.as_ref(), backends, config).await?;
+    Ok(adapter.map(|adapter| (instance, surface, adapter)))
+}
+
+// headless servers and broken drivers can leave the configured backends empty, so look
+// everywhere once before giving up
+async fn request_gpu(window: Option<&Window>, config: &RendererConfig) -> Result<Gpu> {
+    let gpu = match request_gpu_on(window, config.backends, config).await? {
+        Some(gpu) => Some(gpu),
+        None if config.backends != wgpu::BackendBit::all() => {
+            warn!("no {}adapters on {:?}, trying every backend", if config.force_fallback { "software " } else { "" }, config.backends);
+            let gpu = request_gpu_on(window, wgpu::BackendBit::all(), config).await?;
+            if let Some((_, _, adapter)) = &gpu {
+                let info = adapter.get_info();
+                info!("falling back to {} on {:?}", info.name, info.backend);
+            }
+            gpu
+        },
+        None => None,
+    };
+
+    if let Some(gpu) = gpu {
+        let info = gpu.2.get_info();
+        if is_software_adapter(&info) {
+            info!("{} is a software rasterizer, expect it to be slow", info.name);
+        }
+        return Ok(gpu);
+    }
+
+    if config.force_fallback {
+        return Err(anyhow!("couldn't find a software adapter on any backend. mesa's llvmpipe (gl) or lavapipe (vulkan) will do"));
+    }
+
+    Err(anyhow!("couldn't find an adapter on any backend (asked for {:?}). the gl backend (`--backend gl`) \
+        might work on machines without Vulkan, Metal or DirectX drivers", config.backends))
+}
+
+// starts a pass drawing into `target` (resolving into `resolve_target` if it's multisampled),
+// optionally depth tested against `depth` (which gets cleared)
+fn begin_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    target: &'a wgpu::TextureView,
+    resolve_target: Option<&'a wgpu::TextureView>,
+    load: wgpu::LoadOp<wgpu::Color>,
+    depth: Option<&'a wgpu::TextureView>,
+) -> wgpu::RenderPass<'a> {
+    // the attachments are a slice on the stack borrowing this frame's views, so there's no vec to
+    // keep between frames; encoders can't be kept either, finish() consumes them
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        color_attachments: &[
+            wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load,
+                    store: true,
+                }
+            }
+        ],
+        depth_stencil_attachment: depth.map(|view| wgpu::RenderPassDepthStencilAttachmentDescriptor {
+            attachment: view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+    })
+}
+
+/// How to pick and open the gpu; `Default` is what `Renderer::new` uses.
+#[derive(Clone, Debug)]
+pub struct RendererConfig {
+    pub backends: wgpu::BackendBit,
+    /// Index into `enumerate_adapters(backends)`; `None` lets wgpu pick a high performance one.
+    pub adapter_index: Option<usize>,
+    /// Use a software rasterizer (llvmpipe, lavapipe, WARP...), for output that doesn't depend on the gpu.
+    /// Ignored if `adapter_index` is set.
+    pub force_fallback: bool,
+    /// Features to ask for. Anything the adapter doesn't support is dropped (and logged) rather than failing.
+    pub features: wgpu::Features,
+    /// Falls back to Fifo if the backend can't do it, same as `Renderer::set_present_mode`.
+    pub present_mode: wgpu::PresentMode,
+    /// Format of the swapchain (or offscreen texture), which imgui and the scene render in.
+    /// `None` picks one that works everywhere, and so does anything not in `Renderer::preferred_formats`.
+    pub output_format: Option<wgpu::TextureFormat>,
+    /// Records a wgpu api trace into this directory (created if needed), for wgpu bug reports.
+    /// Only does anything when built with the `trace` feature.
+    pub trace_dir: Option<PathBuf>,
+    /// Asks as little of the gpu as possible, for old or integrated ones that fail otherwise: a low
+    /// power adapter, no optional features, no msaa and always Fifo. Renderers that can't start
+    /// normally retry in safe mode on their own, see `with_config`.
+    pub safe_mode: bool,
+    /// Clears to transparent black instead of the clear color, for windows built with
+    /// `WindowBuilder::with_transparent(true)`. Whether it actually shows through is up to the
+    /// platform: wgpu 0.6 always configures the swapchain with opaque composite alpha, so most
+    /// compositors (Vulkan and DX12 ones especially) ignore the alpha channel anyway. Tonemapping
+    /// writes opaque alpha, so it has to be off too.
+    pub transparent: bool,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        RendererConfig {
+            // PRIMARY => VK, Metal, DX12, BWebGpu
+            backends: wgpu::BackendBit::PRIMARY,
+            adapter_index: None,
+            force_fallback: false,
+            features: wgpu::Features::empty(),
+            present_mode: wgpu::PresentMode::Fifo,
+            output_format: None,
+            trace_dir: None,
+            safe_mode: false,
+            transparent: false,
+        }
+    }
+}
+
+impl RendererConfig {
+    /// This config with `safe_mode` on; `features` and `present_mode` get ignored then.
+    pub fn safe(&self) -> Self {
+        RendererConfig { safe_mode: true, ..self.clone() }
+    }
+}
+
+/// Chainable setup for a `Renderer`: a `RendererConfig` plus the settings that are otherwise
+/// only changeable after it's made.
+#[derive(Clone, Debug, Default)]
+pub struct RendererBuilder {
+    config: RendererConfig,
+    sample_count: Option<u32>,
+    clear_color: Option<wgpu::Color>,
+}
+
+impl RendererBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts over from `config`, keeping the sample count and clear color.
+    pub fn config(mut self, config: RendererConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn backend(mut self, backends: wgpu::BackendBit) -> Self {
+        self.config.backends = backends;
+        self
+    }
+
+    pub fn adapter_index(mut self, index: usize) -> Self {
+        self.config.adapter_index = Some(index);
+        self
+    }
+
+    pub fn force_fallback(mut self, force: bool) -> Self {
+        self.config.force_fallback = force;
+        self
+    }
+
+    pub fn features(mut self, features: wgpu::Features) -> Self {
+        self.config.features = features;
+        self
+    }
+
+    pub fn present_mode(mut self, mode: wgpu::PresentMode) -> Self {
+        self.config.present_mode = mode;
+        self
+    }
+
+    pub fn output_format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.config.output_format = Some(format);
+        self
+    }
+
+    pub fn transparent(mut self, transparent: bool) -> Self {
+        self.config.transparent = transparent;
+        self
+    }
+
+    /// Checked when building, see `Renderer::set_sample_count`.
+    pub fn sample_count(mut self, count: u32) -> Self {
+        self.sample_count = Some(count);
+        self
+    }
+
+    /// Linear, see `Renderer::set_clear_color`.
+    pub fn clear_color(mut self, color: wgpu::Color) -> Self {
+        self.clear_color = Some(color);
+        self
+    }
+
+    pub async fn build(&self, window: &Window) -> Result<Renderer> {
+        let renderer = Renderer::with_config(window, &self.config).await?;
+        self.finish(renderer)
+    }
+
+    /// Like `Renderer::new_headless`.
+    pub async fn build_headless(&self, width: u32, height: u32) -> Result<Renderer> {
+        let renderer = Renderer::new_headless_with_config(width, height, &self.config).await?;
+        self.finish(renderer)
+    }
+
+    fn finish(&self, mut renderer: Renderer) -> Result<Renderer> {
+        if let Some(count) = self.sample_count {
+            renderer.set_sample_count(count)?;
+        }
+        if let Some(color) = self.clear_color {
+            renderer.set_clear_color(color);
+        }
+        Ok(renderer)
+    }
+}
+
+impl Renderer {
+    pub async fn new(window: &Window) -> Result<Self> {
+        Self::builder().build(window).await
+    }
+
+    pub fn builder() -> RendererBuilder {
+        RendererBuilder::new()
+    }
+
+    pub async fn new_with_backend(window: &Window, backends: wgpu::BackendBit) -> Result<Self> {
+        Self::with_config(window, &RendererConfig { backends, ..Default::default() }).await
+    }
+
+    pub async fn new_with_adapter_index(window: &Window, index: usize) -> Result<Self> {
+        Self::with_config(window, &RendererConfig { adapter_index: Some(index), ..Default::default() }).await
+    }
+
+    /// Opens the gpu as `config` says, and if that fails tries once more with `config.safe()`
+    /// before giving up.
+    pub async fn with_config(window: &Window, config: &RendererConfig) -> Result<Self> {
+        match Self::with_exact_config(window, config).await {
+            Err(e) if !config.safe_mode => {
+                warn!("{:?}", e);
+                warn!("couldn't start the renderer normally, retrying in safe mode");
+                Self::with_exact_config(window, &config.safe()).await.context("couldn't start the renderer, even in safe mode")
+            },
+            result => result,
+        }
+    }
+
+    async fn with_exact_config(window: &Window, config: &RendererConfig) -> Result<Self> {
+        let (instance, surface, adapter) = request_gpu(Some(window), config).await?;
+        Self::from_adapter(instance, surface, adapter, window.inner_size(), config).await
+    }
+
+    /// Rebuilds everything from the instance down for `window`, e.g. after the gpu was reset and the
+    /// device lost. Settings like the clear color and frame cap carry over, but anything created on the
+    /// old device (including the `ImguiState`'s textures, see `ImguiState::recover`) is gone, and so is
+    /// the pre-ui pass, which likely holds some.
+    pub async fn recover(&mut self, window: &Window) -> Result<()> {
+        warn!("rebuilding the renderer from scratch");
+
+        // a window can only have one swapchain at a time
+        if let Output::Window { swapchain, .. } = &mut self.output {
+            *swapchain = None;
+        }
+
+        let config = RendererConfig {
+            present_mode: self.present_mode(),
+            ..self.config.clone()
+        };
+        let mut fresh = Self::with_config(window, &config).await?;
+        // unless it only came back in safe mode, which should stick
+        if !fresh.config.safe_mode {
+            fresh.config = self.config.clone();
+        }
+        fresh.set_sample_count(self.sample_count)?;
+        fresh.set_render_scale(self.render_scale);
+        fresh.set_tonemapping(self.tonemapping);
+        fresh.set_exposure(self.exposure);
+        fresh.set_ui_viewport(self.ui_viewport);
+        fresh.triangle_shaders = self.triangle_shaders.take();
+        fresh.reload_triangle();
+        fresh.show_triangle = self.show_triangle;
+        fresh.set_show_particles(self.show_particles());
+        fresh.wireframe = self.wireframe;
+        fresh.keep_last_frame = self.keep_last_frame;
+        fresh.camera = self.camera.clone();
+        fresh.cursor_override = self.cursor_override;
+        fresh.clear_color = self.clear_color;
+        fresh.labels = std::mem::replace(&mut self.labels, FrameLabels::new(""));
+        fresh.frame_cap = self.frame_cap;
+        fresh.unfocused_frame_cap = self.unfocused_frame_cap;
+        fresh.focused = self.focused;
+        fresh.max_frame_delta = self.max_frame_delta;
+        fresh.on_resize = self.on_resize.take();
+        fresh.delta_smoothing = self.delta_smoothing;
+        fresh.time_source = self.time_source;
+        fresh.scale_override = self.scale_override;
+
+        *self = fresh;
+        Ok(())
+    }
+
+    /// Another renderer for `window` on this one's device, so resources can be shared between
+    /// windows. Starts out with the same config and present mode, but nothing else carries over.
+    /// `recover` gives a renderer a device of its own, so after a device loss each one sharing it
+    /// has to be recovered, and they don't share anymore.
+    pub fn for_window(&self, window: &Window) -> Result<Self> {
+        let surface = unsafe { self.instance.create_surface(window) };
+        let config = RendererConfig {
+            present_mode: self.present_mode(),
+            ..self.config.clone()
+        };
+
+        let mut renderer = Self::with_gpu(
+            self.instance.clone(),
+            self.adapter.clone(),
+            self.device.clone(),
+            self.queue.clone(),
+            Some(surface),
+            window.inner_size(),
+            &config,
+        );
+        renderer.config = self.config.clone();
+        Ok(renderer)
+    }
+
+    /// A renderer with no window, drawing into a `width`x`height` texture instead. Use it with
+    /// `ImguiState::new_headless` and `render_headless`.
+    pub async fn new_headless(width: u32, height: u32) -> Result<Self> {
+        Self::builder().build_headless(width, height).await
+    }
+
+    /// Retries in safe mode like `with_config`.
+    pub async fn new_headless_with_config(width: u32, height: u32, config: &RendererConfig) -> Result<Self> {
+        match Self::new_headless_with_exact_config(width, height, config).await {
+            Err(e) if !config.safe_mode => {
+                warn!("{:?}", e);
+                warn!("couldn't start the renderer normally, retrying in safe mode");
+                Self::new_headless_with_exact_config(width, height, &config.safe()).await
+                    .context("couldn't start the renderer, even in safe mode")
+            },
+            result => result,
+        }
+    }
+
+    async fn new_headless_with_exact_config(width: u32, height: u32, config: &RendererConfig) -> Result<Self> {
+        let (instance, _, adapter) = request_gpu(None, config).await?;
+        Self::from_adapter(instance, None, adapter, PhysicalSize::new(width, height), config).await
+    }
+
+    async fn from_adapter(
+        instance: wgpu::Instance,
+        surface: Option<wgpu::Surface>,
+        adapter: wgpu::Adapter,
+        size: PhysicalSize<u32>,
+        config: &RendererConfig,
+    ) -> Result<Self> {
+        if config.safe_mode {
+            warn!("safe mode: low power adapter, no optional features, no msaa, vsync on");
+        }
+
+        // asking for a feature the adapter doesn't have fails request_device outright
+        let requested_features = if config.safe_mode { wgpu::Features::empty() } else { config.features };
+        let features = requested_features & adapter.features();
+        let dropped = requested_features - features;
+        if !requested_features.is_empty() {
+            info!("requested features {:?}, granted {:?}", requested_features, features);
+        }
+        if !dropped.is_empty() {
+            warn!("adapter doesn't support {:?}, continuing without", dropped);
+        }
+
+        // and the device is an open connection to it
+        let trace_dir = config.trace_dir.as_deref().filter(|dir| {
+            if !cfg!(feature = "trace") {
+                warn!("not recording a wgpu trace to {}, built without the trace feature", dir.display());
+                return false;
+            }
+            match std::fs::create_dir_all(dir) {
+                Ok(()) => {
+                    info!("recording a wgpu trace to {}", dir.display());
+                    true
+                },
+                Err(e) => {
+                    warn!("not recording a wgpu trace, couldn't create {}: {}", dir.display(), e);
+                    false
+                },
+            }
+        });
+
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features,
+                // there's no downlevel_defaults in this wgpu, but its defaults are already the
+                // WebGPU baseline every adapter has to meet, so safe mode has nothing lower to ask for
+                limits: wgpu::Limits::default(),
+                shader_validation: true,
+            },
+            trace_dir,
+        ).await?;
+
+        let renderer = Self::with_gpu(Rc::new(instance), Rc::new(adapter), Rc::new(device), Rc::new(queue), surface, size, config);
+        renderer.log_gpu_info();
+
+        Ok(renderer)
+    }
+
+    fn with_gpu(
+        instance: Rc<wgpu::Instance>,
+        adapter: Rc<wgpu::Adapter>,
+        device: Rc<wgpu::Device>,
+        queue: Rc<wgpu::Queue>,
+        surface: Option<wgpu::Surface>,
+        size: PhysicalSize<u32>,
+        config: &RendererConfig,
+    ) -> Self {
+        let format = checked_output_format(adapter.get_info().backend, surface.is_some(), config.output_format);
+        info!("output format is {:?}", format);
+
+        let swapchain_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode: if config.safe_mode { wgpu::PresentMode::Fifo } else { checked_present_mode(adapter.get_info().backend, config.present_mode) },
+        };
+        let output = create_output(&device, surface, &swapchain_desc);
+
+        let sample_count = 1;
+        let depth = Texture::create_depth(&device, size, sample_count);
+
+        let camera_uniform = CameraUniform::new(&device);
+        let triangle = TrianglePipeline::new(&device, swapchain_desc.format, sample_count, &camera_uniform.bind_group_layout);
+        let blit = BlitPipeline::new(&device, swapchain_desc.format);
+        let tonemap = TonemapPipeline::new(&device, swapchain_desc.format);
+        tonemap.set_exposure(&queue, DEFAULT_EXPOSURE);
+
+        let last_frame_ts = Instant::now();
+
+        Renderer {
+            instance,
+            adapter,
+            device,
+            queue,
+            swapchain_desc,
+            output,
+            config: config.clone(),
+            size,
+            depth,
+            sample_count,
+            msaa_target: None,
+            render_scale: 1.0,
+            scaled_target: None,
+            ui_viewport: None,
+            ui_target: None,
+            blit,
+            tonemapping: false,
+            exposure: DEFAULT_EXPOSURE,
+            tonemap,
+            hdr_target: None,
+            pre_ui_pass: RefCell::new(None),
+            on_resize: None,
+            triangle,
+            triangle_shaders: None,
+            show_triangle: true,
+            particles: None,
+            wireframe: false,
+            camera: Camera::default(),
+            camera_uniform,
+            pending_capture: None,
+            capture_sequence: None,
+            readback_pool: ReadbackPool::default(),
+            keep_last_frame: false,
+            last_frame: None,
+            labels: FrameLabels::new(""),
+            clear_color: wgpu::Color {
+                r: 0.1,
+                g: 0.2,
+                b: 0.3,
+                a: 1.0,
+            },
+            last_error: None,
+            is_minimized: false,
+            last_frame_ts,
+            frame_cap: None,
+            unfocused_frame_cap: Some(DEFAULT_UNFOCUSED_FRAME_CAP),
+            focused: true,
+            max_frame_delta: DEFAULT_MAX_FRAME_DELTA,
+            delta_smoothing: DeltaSmoothing::default(),
+            time_source: TimeSource::default(),
+            scale_override: None,
+            smoothed_delta: Duration::default(),
+            paused: false,
+            step_requested: false,
+            recent_frames: FrameStats::new(FPS_WINDOW),
+            frame_stats: FrameStats::new(FRAME_STATS_WINDOW),
+            skip_frame_stats: false,
+            last_cursor: None,
+            cursor_override: None,
+        }
+    }
+
+    /// Logs one line describing the adapter and device, for pasting into bug reports.
+    pub fn log_gpu_info(&self) {
+        let adapter = self.adapter_info();
+        // wgpu doesn't report a driver version yet; the pci ids are the next best thing
+        info!("gpu info: adapter={:?} backend={:?} type={:?} vendor={:#06x} device={:#06x} features={:?} limits={:?}",
+            adapter.name,
+            adapter.backend,
+            adapter.device_type,
+            adapter.vendor,
+            adapter.device,
+            self.device.features(),
+            self.device.limits());
+    }
+
+    /// The instance the renderer was created from, e.g. for `enumerate_adapters`.
+    pub fn instance(&self) -> &wgpu::Instance {
+        &self.instance
+    }
+
+    pub fn adapter_info(&self) -> wgpu::AdapterInfo {
+        self.adapter.get_info()
+    }
+
+    /// For making buffers, textures and pipelines of your own.
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    /// Blocks until the gpu has finished everything submitted so far, on every renderer sharing the device.
+    pub fn wait_for_gpu_idle(&self) {
+        // an empty submit makes sure anything written with the queue but not yet submitted goes too
+        self.queue.submit(std::iter::empty());
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+
+    /// Finishes the gpu's work and frees everything this renderer made before the device itself, so
+    /// nothing's still in flight or mapped when the device goes. Free the `ImguiState`'s textures
+    /// (`ImguiState::free_textures`) and drop it first, and keep the window around until after.
+    pub fn shutdown(self) {
+        self.wait_for_gpu_idle();
+
+        // wgpu only really destroys dropped resources once the device is polled again
+        let (instance, adapter, device, queue) = (self.instance.clone(), self.adapter.clone(), self.device.clone(), self.queue.clone());
+        drop(self);
+        device.poll(wgpu::Maintain::Wait);
+        drop(queue);
+        drop(device);
+        drop(adapter);
+        drop(instance);
+    }
+
+    /// Drives the device: `BufferSlice::map_async` futures only complete once it's been polled.
+    /// Every frame does a `Maintain::Poll` after submitting, so a readback started one frame is
+    /// usually ready a frame or two later without calling this; `Maintain::Wait` blocks until
+    /// everything submitted is done, for when it has to be ready now (or when nothing's being
+    /// rendered, like in a headless tool).
+    pub fn poll(&self, maintain: wgpu::Maintain) {
+        self.device.poll(maintain);
+    }
+
+    /// The features the device was actually created with.
+    pub fn features(&self) -> wgpu::Features {
+        self.device.features()
+    }
+
+    /// The size of the swapchain (or offscreen texture), in physical pixels.
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Average frame time over the last few frames.
+    pub fn frame_time(&self) -> Duration {
+        self.recent_frames.avg()
+    }
+
+    /// Real (unclamped) frame times over the last few seconds.
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    /// Keeps the last `frames` frame times (240 by default) in `frame_stats`, starting over empty.
+    /// The fps counter averages over its own few frames either way.
+    pub fn set_frame_stats_window(&mut self, frames: usize) {
+        self.frame_stats = FrameStats::new(frames.max(1));
+    }
+
+    /// `frame_stats` and what it was measured on, as a `StatsReport`.
+    pub fn stats_report(&self) -> StatsReport {
+        StatsReport::new(&self.frame_stats, self.size.width, self.size.height, &self.adapter_info())
+    }
+
+    /// Writes `stats_report` to `path` as pretty printed json.
+    pub fn dump_stats_json(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.stats_report()).context("couldn't serialize the frame stats")?;
+        std::fs::write(path, json).with_context(|| format!("couldn't write {}", path.display()))
+    }
+
+    pub fn reset_frame_stats(&mut self) {
+        self.frame_stats.reset();
+    }
+
+    pub fn current_fps(&self) -> f32 {
+        let frame_time = self.frame_time().as_secs_f32();
+        if frame_time > 0.0 { 1.0 / frame_time } else { 0.0 }
+    }
+
+    /// How long the gpu took to render the last frame, if the device can tell us.
+    pub fn last_gpu_time(&self) -> Option<Duration> {
+        // wgpu 0.6 has no query sets (and no Features::TIMESTAMP_QUERY to request), so there's
+        // nothing to write timestamps into yet. callers should already be handling None.
+        None
+    }
+
+    /// Limits rendering to `fps` frames per second; `None` renders as fast as the present mode allows.
+    pub fn set_frame_cap(&mut self, fps: Option<u32>) {
+        self.frame_cap = fps.filter(|&fps| fps > 0);
+    }
+
+    pub fn frame_cap(&self) -> Option<u32> {
+        self.frame_cap
+    }
+
+    /// Allocation counts and sizes from the backend, if it can tell us.
+    pub fn memory_report(&self) -> Option<String> {
+        // wgpu 0.6 doesn't expose any allocator stats (generate_report only shows up in later
+        // versions), so there's nothing to format yet
+        None
+    }
+
+    /// Frame cap used instead while the window is unfocused (10 fps by default); `None` doesn't throttle.
+    pub fn set_unfocused_frame_cap(&mut self, fps: Option<u32>) {
+        self.unfocused_frame_cap = fps.filter(|&fps| fps > 0);
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        // time spent in the background isn't a frame
+        if focused && !self.focused {
+            self.last_frame_ts = Instant::now();
+        }
+        self.focused = focused;
+    }
+
+    /// Times the next frame from now, so a stretch of not drawing anything (like idling until the
+    /// next event) doesn't get counted as one long frame. That frame is left out of the frame
+    /// stats too, since it'd be that much shorter.
+    pub fn restart_frame_timer(&mut self) {
+        self.last_frame_ts = Instant::now();
+        self.skip_frame_stats = true;
+    }
+
+    /// Caps the frame delta handed to imgui and the app (100ms by default).
+    pub fn set_max_frame_delta(&mut self, max: Duration) {
+        self.max_frame_delta = max;
+    }
+
+    /// How imgui's delta gets smoothed (a light moving average by default).
+    pub fn set_delta_smoothing(&mut self, smoothing: DeltaSmoothing) {
+        self.delta_smoothing = smoothing;
+    }
+
+    pub fn delta_smoothing(&self) -> DeltaSmoothing {
+        self.delta_smoothing
+    }
+
+    /// Real time by default; a fixed step makes the frames' deltas (and so the animation) reproducible.
+    pub fn set_time_source(&mut self, source: TimeSource) {
+        self.time_source = source;
+    }
+
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
+    }
+
+    /// When the next frame is due under the frame cap, or `None` if there's no cap.
+    pub fn next_frame_deadline(&self) -> Option<Instant> {
+        let cap = match (self.focused, self.frame_cap, self.unfocused_frame_cap) {
+            (false, Some(cap), Some(unfocused)) => Some(cap.min(unfocused)),
+            (false, cap, unfocused) => cap.or(unfocused),
+            (true, cap, _) => cap,
+        };
+        cap.map(|fps| self.last_frame_ts + Duration::from_secs(1) / fps)
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// While paused, lets the next frame through with its real delta.
+    pub fn step(&mut self) {
+        self.step_requested = true;
+    }
+
+    /// Runs `pass` every frame after the scene is drawn and before imgui, on the same encoder.
+    ///
+    /// The view is the final frame (full resolution, single sampled, in the output format) with the
+    /// scene already in it, so passes should use `LoadOp::Load` to draw over it rather than clearing.
+    /// With tonemapping on it's the scene's `HDR_FORMAT` texture instead, at the scene's size, and
+    /// gets tonemapped along with the scene afterwards. There's no depth attachment.
+    pub fn set_pre_ui_pass(&mut self, pass: UserPass) {
+        *self.pre_ui_pass.get_mut() = Some(pass);
+    }
+
+    pub fn clear_pre_ui_pass(&mut self) {
+        *self.pre_ui_pass.get_mut() = None;
+    }
+
+    /// Runs `callback` after every resize, so size-dependent resources outside the renderer can be
+    /// rebuilt. Being minimized isn't a resize; the size just stays what it was.
+    pub fn set_on_resize(&mut self, callback: ResizeCallback) {
+        self.on_resize = Some(callback);
+    }
+
+    pub fn clear_on_resize(&mut self) {
+        self.on_resize = None;
+    }
+
+    /// Names this renderer's encoders and debug groups "`prefix` ...", for telling apps apart in
+    /// RenderDoc and the like. Empty (the default) leaves them unprefixed.
+    pub fn set_debug_prefix(&mut self, prefix: &str) {
+        self.labels = FrameLabels::new(prefix);
+    }
+
+    pub fn set_show_triangle(&mut self, show: bool) {
+        self.show_triangle = show;
+    }
+
+    pub fn wireframe(&self) -> bool {
+        self.wireframe
+    }
+
+    /// Draws the triangle and particles as outlines, for seeing what the geometry actually is.
+    /// Works everywhere: it's drawn as lines rather than with a line polygon mode, which this wgpu
+    /// doesn't have.
+    pub fn set_wireframe(&mut self, wireframe: bool) {
+        self.wireframe = wireframe;
+    }
+
+    pub fn show_particles(&self) -> bool {
+        self.particles.is_some()
+    }
+
+    /// Shows a little fountain of particles in the scene, moved by each frame's delta time; off by
+    /// default. Turning it off throws them away, so it starts over from nothing next time.
+    pub fn set_show_particles(&mut self, show: bool) {
+        match (show, &self.particles) {
+            (true, None) => self.particles = Some(Particles::new(&self.device)),
+            (false, Some(_)) => self.particles = None,
+            _ => (),
+        }
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    /// Layout of `camera_bind_group`, for building pipelines that use the camera. It holds the
+    /// view-projection matrix as a column major `mat4` uniform at binding 0, visible to vertex shaders.
+    pub fn camera_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.camera_uniform.bind_group_layout
+    }
+
+    /// Updated with the camera's matrix for the current frame before anything gets drawn.
+    pub fn camera_bind_group(&self) -> &wgpu::BindGroup {
+        &self.camera_uniform.bind_group
+    }
+
+    /// Shows `icon` over the window whatever imgui asks for (including when it wants the cursor
+    /// hidden), e.g. a crosshair while picking something in the scene. `None` hands it back to imgui.
+    pub fn set_cursor_override(&mut self, icon: Option<CursorIcon>) {
+        self.cursor_override = icon;
+    }
+
+    pub fn cursor_override(&self) -> Option<CursorIcon> {
+        self.cursor_override
+    }
+
+    pub fn clear_color(&self) -> wgpu::Color {
+        self.clear_color
+    }
+
+    // what the scene and the ui viewport actually get cleared to. imgui blends its alpha in with
+    // OneMinusDstAlpha, One, so over transparent black it comes out premultiplied, which is what
+    // compositors expect
+    fn effective_clear_color(&self) -> wgpu::Color {
+        if self.config.transparent { wgpu::Color::TRANSPARENT } else { self.clear_color }
+    }
+
+    /// See `RendererConfig::transparent`.
+    pub fn is_transparent(&self) -> bool {
+        self.config.transparent
+    }
+
+    /// `color` is linear, like everything else the shaders write: an sRGB output encodes it on the
+    /// way out, so colors from a picker need `srgb_to_linear` first. With a non-sRGB `output_format`
+    /// it's stored as is.
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = color;
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.swapchain_desc.present_mode
+    }
+
+    pub fn supports_present_mode(&self, mode: wgpu::PresentMode) -> bool {
+        self.supported_present_modes().contains(&mode)
+    }
+
+    /// The output formats this renderer could have been made with, best first. They're all 8 bits
+    /// per channel, rgba or bgra, which is what screenshots can read back.
+    pub fn preferred_formats(&self) -> &'static [wgpu::TextureFormat] {
+        let has_surface = matches!(self.output, Output::Window { .. });
+        preferred_formats(self.adapter_info().backend, has_surface)
+    }
+
+    pub fn output_format(&self) -> wgpu::TextureFormat {
+        self.swapchain_desc.format
+    }
+
+    /// Our best guess at which present modes the backend can do; Fifo is always there.
+    pub fn supported_present_modes(&self) -> &'static [wgpu::PresentMode] {
+        if self.config.safe_mode {
+            return &[wgpu::PresentMode::Fifo];
+        }
+        supported_present_modes(self.adapter_info().backend)
+    }
+
+    /// Stays on Fifo in safe mode.
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) {
+        let mode = if self.config.safe_mode { wgpu::PresentMode::Fifo } else { checked_present_mode(self.adapter_info().backend, mode) };
+        debug!("switching present mode to {:?}", mode);
+        self.swapchain_desc.present_mode = mode;
+        self.recreate_swapchain();
+    }
+
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        // minimizing (on windows at least) resizes us to 0x0, which isn't a valid swapchain size
+        if new_size.width == 0 || new_size.height == 0 {
+            debug!("window minimized, not resizing to {:?}", new_size);
+            self.is_minimized = true;
+            return;
+        }
+
+        debug!("resizing to {:?}", new_size);
+        self.is_minimized = false;
+        self.size = new_size;
+        self.swapchain_desc.width = new_size.width;
+        self.swapchain_desc.height = new_size.height;
+        self.recreate_swapchain();
+        self.recreate_render_targets();
+        if let Some(callback) = &mut self.on_resize {
+            callback(new_size, &self.device, &self.queue);
+        }
+        // don't count however long the window sat in a resize/un-minimize as frame time
+        self.last_frame_ts = Instant::now();
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Uses `scale` instead of `window.scale_factor()` for imgui's sizes, input and fonts, for
+    /// displays that report the wrong one. `None` goes back to the reported factor.
+    pub fn set_scale_override(&mut self, scale: Option<f64>) {
+        self.scale_override = scale.filter(|&scale| scale > 0.0);
+    }
+
+    pub fn scale_override(&self) -> Option<f64> {
+        self.scale_override
+    }
+
+    /// The scale factor in use for `window`: the override, or failing that what it reports.
+    pub fn scale_factor(&self, window: &Window) -> f64 {
+        self.scale_override.unwrap_or_else(|| window.scale_factor())
+    }
+
+    /// Resizes for a `ScaleFactorChanged` and returns the size the window should take, for
+    /// setting its `new_inner_size` to. winit suggests a size worked out from the reported factor,
+    /// which means nothing with an override set, so then the window keeps the size it has.
+    pub fn resize_to_scale(&mut self, window: &Window, suggested: PhysicalSize<u32>) -> PhysicalSize<u32> {
+        let size = if self.scale_override.is_some() { window.inner_size() } else { suggested };
+        self.resize(size);
+        size
+    }
+
+    pub fn set_sample_count(&mut self, count: u32) -> Result<()> {
+        if !SUPPORTED_SAMPLE_COUNTS.contains(&count) {
+            return Err(anyhow!("unsupported sample count {}, must be one of {:?}", count, SUPPORTED_SAMPLE_COUNTS));
+        }
+
+        if self.config.safe_mode && count > 1 {
+            warn!("not switching to {}x msaa in safe mode", count);
+            return Ok(());
+        }
+
+        debug!("switching to {}x msaa", count);
+        self.sample_count = count;
+        self.recreate_render_targets();
+        self.rebuild_triangle();
+
+        Ok(())
+    }
+
+    pub fn tonemapping(&self) -> bool {
+        self.tonemapping
+    }
+
+    /// Draws the scene (and the pre-ui pass) into an `HDR_FORMAT` texture and tonemaps it to the
+    /// output before imgui, so colors brighter than 1 roll off instead of clipping. Off by default.
+    pub fn set_tonemapping(&mut self, enabled: bool) {
+        if enabled == self.tonemapping {
+            return;
+        }
+
+        debug!("tonemapping is now {}", if enabled { "on" } else { "off" });
+        if enabled && self.config.transparent {
+            warn!("tonemapping makes the window background opaque");
+        }
+        self.tonemapping = enabled;
+        self.recreate_render_targets();
+        self.rebuild_triangle();
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    /// What the scene gets multiplied by before tonemapping; does nothing with tonemapping off.
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure.max(0.0);
+        self.tonemap.set_exposure(&self.queue, self.exposure);
+    }
+
+    /// What the scene is drawn in, for building pipelines that draw into it: the output format, or
+    /// `HDR_FORMAT` with tonemapping on.
+    pub fn scene_format(&self) -> wgpu::TextureFormat {
+        if self.tonemapping { HDR_FORMAT } else { self.swapchain_desc.format }
+    }
+
+    /// Builds the triangle from SPIR-V files instead of the shaders baked in, and keeps rebuilding
+    /// it whenever they change; see `reload_changed_shaders`.
+    pub fn watch_triangle_shaders(&mut self, vs_path: PathBuf, fs_path: PathBuf) -> Result<()> {
+        let watcher = ShaderWatcher::new(&[vs_path.clone(), fs_path.clone()])?;
+        self.triangle_shaders = Some((watcher, vs_path, fs_path));
+        self.reload_triangle();
+        Ok(())
+    }
+
+    /// Rebuilds pipelines whose watched shaders changed since the last call, returning whether
+    /// any did. Shaders that fail to build get logged and the last good pipeline is kept.
+    pub fn reload_changed_shaders(&mut self) -> bool {
+        match &self.triangle_shaders {
+            Some((watcher, ..)) if watcher.changed() => {
+                self.reload_triangle();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Runs `f`, which creates shaders, pipelines or the like, catching the validation errors wgpu
+    /// panics with. The error gets logged and kept for `last_error`, and `f`'s result is thrown away.
+    ///
+    /// This wgpu has no uncaptured error handler, so errors outside of these (and the renderer's own
+    /// shader reloading) still panic. Mistakes in draw calls usually only show up at submit time, which
+    /// isn't safe to carry on from.
+    pub fn catch_gpu_error<T>(&mut self, f: impl FnOnce(&wgpu::Device, &wgpu::Queue) -> T) -> Option<T> {
+        let (device, queue) = (&self.device, &self.queue);
+        match catch_wgpu_panic(|| f(device, queue)) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                error!("wgpu error: {}", e);
+                self.last_error = Some(e);
+                None
+            },
+        }
+    }
+
+    /// The last wgpu error the renderer caught, see `catch_gpu_error`.
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    pub fn clear_last_error(&mut self) {
+        self.last_error = None;
+    }
+
+    // for a new sample count or scene format
+    fn rebuild_triangle(&mut self) {
+        self.triangle = TrianglePipeline::new(&self.device, self.scene_format(), self.sample_count, &self.camera_uniform.bind_group_layout);
+        self.reload_triangle();
+    }
+
+    fn reload_triangle(&mut self) {
+        if let Some((_, vs_path, fs_path)) = &self.triangle_shaders {
+            match TrianglePipeline::load(&self.device, self.scene_format(), self.sample_count, &self.camera_uniform.bind_group_layout, vs_path, fs_path) {
+                Ok(triangle) => {
+                    info!("loaded triangle shaders from {}", vs_path.parent().unwrap_or(vs_path).display());
+                    self.triangle = triangle;
+                },
+                Err(e) => {
+                    warn!("{:?}; keeping the previous triangle pipeline", e);
+                    self.last_error = Some(format!("{:?}", e));
+                },
+            }
+        }
+    }
+
+    fn recreate_swapchain(&mut self) {
+        match &mut self.output {
+            Output::Window { surface, swapchain } => *swapchain = Some(self.device.create_swap_chain(surface, &self.swapchain_desc)),
+            Output::Texture { .. } => self.output = create_output(&self.device, None, &self.swapchain_desc),
+        }
+    }
+
+    // rebuilds everything that has to match the swapchain size, sample count or scene format
+    fn recreate_render_targets(&mut self) {
+        let scene_size = self.scene_size();
+        let scene_format = self.scene_format();
+        let attachment = wgpu::TextureUsage::OUTPUT_ATTACHMENT;
+
+        self.depth = Texture::create_depth(&self.device, scene_size, self.sample_count);
+
+        self.msaa_target = if self.sample_count > 1 {
+            Some(Texture::create(&self.device, "msaa color texture", scene_size, scene_format, self.sample_count, attachment))
+        } else {
+            None
+        };
+
+        self.hdr_target = if self.tonemapping {
+            let texture = Texture::create(&self.device, "hdr scene texture", scene_size, HDR_FORMAT, 1,
+                attachment | wgpu::TextureUsage::SAMPLED);
+            let bind_group = self.tonemap.bind_source(&self.device, &texture.view);
+            Some((texture, bind_group))
+        } else {
+            None
+        };
+
+        // the tonemap pass stretches the hdr target itself
+        self.scaled_target = if scene_size != self.size && !self.tonemapping {
+            let texture = Texture::create(&self.device, "scaled scene texture", scene_size, self.swapchain_desc.format, 1,
+                attachment | wgpu::TextureUsage::SAMPLED);
+            let bind_group = self.blit.bind_source(&self.device, &texture.view);
+            Some((texture, bind_group))
+        } else {
+            None
+        };
+
+        let ui_rect = self.ui_viewport.and_then(|rect| {
+            let clamped = rect.clamp_to(self.size);
+            if clamped.is_none() {
+                warn!("ui viewport {:?} is outside the {:?} frame, drawing the ui over all of it", rect, self.size);
+            }
+            clamped
+        });
+        self.ui_target = ui_rect.map(|rect| {
+            let texture = Texture::create(&self.device, "ui viewport texture", PhysicalSize::new(rect.width, rect.height),
+                self.swapchain_desc.format, 1, attachment | wgpu::TextureUsage::SAMPLED);
+            let bind_group = self.blit.bind_source(&self.device, &texture.view);
+            (rect, texture, bind_group)
+        });
+    }
+
+    /// Draws imgui into just `rect` of the frame rather than all of it, e.g. to put it in a panel of
+    /// some other ui. The rect is cleared to the clear color first, so the scene doesn't show through
+    /// it, and the rest of the frame is left alone. It gets clamped to the frame, including after
+    /// resizes; `None` (the default) goes back to the whole frame.
+    pub fn set_ui_viewport(&mut self, rect: Option<Rect>) {
+        self.ui_viewport = rect;
+        self.recreate_render_targets();
+    }
+
+    /// The rect imgui is being drawn into, after clamping, or `None` for the whole frame.
+    pub fn ui_viewport(&self) -> Option<Rect> {
+        self.ui_target.as_ref().map(|(rect, ..)| *rect)
+    }
+
+    // the scene gets drawn at this size and then stretched to the output
+    pub(crate) fn scene_size(&self) -> PhysicalSize<u32> {
+        let scale = |n: u32| ((n as f32 * self.render_scale).round() as u32).max(1);
+        PhysicalSize::new(scale(self.size.width), scale(self.size.height))
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Draws the scene at `scale` times the window size (clamped to (0, 1]) and upscales it;
+    /// imgui is always drawn at full resolution.
+    pub fn set_render_scale(&mut self, scale: f32) {
+        // also catches nan
+        self.render_scale = if scale > 0.0 { scale.min(1.0) } else { 1.0 };
+        debug!("render scale is now {}", self.render_scale);
+        self.recreate_render_targets();
+    }
+
+    /// Without an `ImguiState` the frame is just the scene and the pre-ui pass, and `app` isn't called.
+    pub fn render(&mut self, window: &Window, imstate: Option<&mut ImguiState>, app: &mut dyn App) -> Result<(), RenderError> {
+        self.render_with(Some(window), imstate, app)
+    }
+
+    /// Renders a frame into the offscreen texture of a `new_headless` renderer.
+    pub fn render_headless(&mut self, imstate: Option<&mut ImguiState>, app: &mut dyn App) -> Result<(), RenderError> {
+        self.render_with(None, imstate, app)
+    }
+
+    fn render_with(&mut self, window: Option<&Window>, imstate: Option<&mut ImguiState>, app: &mut dyn App) -> Result<(), RenderError> {
+        match self.begin_with(window, imstate)? {
+            Some(frame) => {
+                if let Some(ui) = frame.ui() {
+                    app.ui(ui, frame.delta());
+                }
+                self.end_frame(frame)
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Starts a frame for `window`: the scene (and the pre-ui pass) is already encoded, and imgui is
+    /// ready for drawing to. Anything else can be recorded onto the frame's encoder before handing
+    /// it to `end_frame`, which draws imgui on top and submits. `None` means there's nothing to draw
+    /// into right now (the window is minimized). Without an `ImguiState` there's no imgui to draw.
+    pub fn begin_frame<'a>(&mut self, window: &'a Window, imstate: Option<&'a mut ImguiState>) -> Result<Option<FrameContext<'a>>, RenderError> {
+        self.begin_with(Some(window), imstate)
+    }
+
+    /// `begin_frame` for a `new_headless` renderer.
+    pub fn begin_headless_frame<'a>(&mut self, imstate: Option<&'a mut ImguiState>) -> Result<Option<FrameContext<'a>>, RenderError> {
+        self.begin_with(None, imstate)
+    }
+
+    fn begin_with<'a>(&mut self, window: Option<&'a Window>, mut imstate: Option<&'a mut ImguiState>) -> Result<Option<FrameContext<'a>>, RenderError> {
+        if self.is_minimized {
+            return Ok(None);
+        }
+
+        let now = Instant::now();
+        let delta_t = now - self.last_frame_ts;
+        let frame = match &mut self.output {
+            Output::Window { swapchain: Some(swapchain), .. } => Some(swapchain.get_current_frame()?),
+            Output::Window { swapchain: None, .. } => return Err(wgpu::SwapChainError::Lost.into()),
+            Output::Texture { .. } => None,
+        };
+
+        self.last_frame_ts = now;
+
+        if !std::mem::take(&mut self.skip_frame_stats) {
+            self.recent_frames.push(delta_t);
+            self.frame_stats.push(delta_t);
+        }
+
+        // the fps counter keeps the real number, but the ui shouldn't see a huge step after idling.
+        // a fixed step is what was asked for, so it's left alone
+        let delta_t = match self.time_source {
+            TimeSource::Real => delta_t.min(self.max_frame_delta),
+            TimeSource::Fixed { step } => step,
+        };
+        let delta_t = if self.paused && !self.step_requested { Duration::default() } else { delta_t };
+        self.step_requested = false;
+        // pauses don't count, or resuming would ease back in from nothing
+        if delta_t != Duration::default() {
+            self.smoothed_delta = self.delta_smoothing.apply(self.smoothed_delta, delta_t);
+        }
+        let smoothed_delta = if delta_t == Duration::default() { delta_t } else { self.smoothed_delta };
+        if let Some(imstate) = imstate.as_deref_mut() {
+            imstate.ctx.io_mut().update_delta_time(smoothed_delta);
+            // imgui asserts on a zero delta (and update_delta_time doesn't guard against it)
+            if smoothed_delta == Duration::default() {
+                imstate.ctx.io_mut().delta_time = f32::MIN_POSITIVE;
+            }
+
+            imstate.prepare_frame(&self.device, &self.queue, window, self.size, self.ui_viewport(), self.scale_override).map_err(|e| RenderError::ImguiFramePrepError { source: e })?;
+        }
+
+        let capture_path = self.capture_path();
+        let target = match (frame, &self.output) {
+            // the swapchain texture can't be copied out of, so draw somewhere that can and copy it over at the end
+            (Some(frame), _) if capture_path.is_some() || self.keep_last_frame => {
+                let size = wgpu::Extent3d { width: self.size.width, height: self.size.height, depth: 1 };
+                let texture = match self.last_frame.take() {
+                    Some(last) if last.size == size => last,
+                    _ => Texture::create(&self.device, "capture texture", self.size, self.swapchain_desc.format, 1,
+                        wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC | wgpu::TextureUsage::SAMPLED),
+                };
+                FrameTarget::Capture { frame, texture }
+            },
+            (Some(frame), _) => FrameTarget::Swapchain(frame),
+            (None, Output::Texture { texture, .. }) => FrameTarget::Offscreen(texture.texture.create_view(&wgpu::TextureViewDescriptor::default())),
+            (None, Output::Window { .. }) => unreachable!("window output always has a frame"),
+        };
+
+        // used to encode series of gpu operations!
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some(&self.labels.encoder),
+        });
+
+        let aspect = self.size.width as f32 / self.size.height as f32;
+        self.camera_uniform.write(&self.queue, self.camera.view_proj(aspect));
+        if let Some(particles) = &mut self.particles {
+            particles.update(&self.queue, delta_t);
+        }
+        self.encode_scene(&mut encoder, target.view());
+
+        Ok(Some(FrameContext {
+            imgui: imstate.map(|imstate| (imstate.ctx.frame(), &mut imstate.renderer)),
+            encoder,
+            target,
+            capture_path,
+            delta: delta_t,
+            smoothed_delta,
+            window,
+        }))
+    }
+
+    /// Draws imgui over everything recorded since `begin_frame`, submits, and presents.
+    pub fn end_frame(&mut self, frame: FrameContext) -> Result<(), RenderError> {
+        let FrameContext { imgui, mut encoder, target, capture_path, window, .. } = frame;
+
+        // done here rather than by WinitPlatform::prepare_render, which can't be overridden and caches
+        // what it set. no cursor from imgui means hidden, and so does imgui drawing its own
+        if let Some(window) = window {
+            let cursor = match (self.cursor_override, &imgui) {
+                (Some(icon), _) => Some(icon),
+                (None, None) => Some(CursorIcon::Default),
+                (None, Some((ui, _))) if ui.io().mouse_draw_cursor => None,
+                (None, Some((ui, _))) => ui.mouse_cursor().map(winit_cursor),
+            };
+            if self.last_cursor != Some(cursor) {
+                self.last_cursor = Some(cursor);
+                match cursor {
+                    Some(icon) => {
+                        window.set_cursor_visible(true);
+                        window.set_cursor_icon(icon);
+                    },
+                    None => window.set_cursor_visible(false),
+                }
+            }
+        }
+
+        if let Some((ui, imgui_renderer)) = imgui {
+            // imgui draws on top of the scene and doesn't need depth. it sets its own scissor rects
+            // assuming it has the whole attachment, so a viewport gets its own texture
+            let (view, load) = match &self.ui_target {
+                Some((_, texture, _)) => (&texture.view, wgpu::LoadOp::Clear(self.effective_clear_color())),
+                None => (target.view(), wgpu::LoadOp::Load),
+            };
+            {
+                let mut rpass = begin_pass(&mut encoder, view, None, load, None);
+                rpass.push_debug_group(&self.labels.imgui);
+                let result = imgui_renderer.render(ui.render(), &self.queue, &self.device, &mut rpass);
+                rpass.pop_debug_group();
+
+                if let Err(e) = result {
+                    if let Some(path) = &capture_path {
+                        warn!("dropped a frame that couldn't draw its ui; {} will be captured from the next one", path.display());
+                    }
+                    return Err(RenderError::ImguiRendererError(e));
+                }
+            }
+
+            if let Some((rect, _, source)) = &self.ui_target {
+                let mut rpass = begin_pass(&mut encoder, target.view(), None, wgpu::LoadOp::Load, None);
+                rpass.push_debug_group(&self.labels.ui_viewport);
+                rpass.set_viewport(rect.x as f32, rect.y as f32, rect.width as f32, rect.height as f32, 0.0, 1.0);
+                rpass.set_scissor_rect(rect.x, rect.y, rect.width, rect.height);
+                self.blit.draw(&mut rpass, source);
+                rpass.pop_debug_group();
+            }
+        }
+
+        if let FrameTarget::Capture { frame, texture } = &target {
+            let source = self.blit.bind_source(&self.device, &texture.view);
+            let mut rpass = begin_pass(&mut encoder, &frame.output.view, None, wgpu::LoadOp::Load, None);
+            rpass.push_debug_group(&self.labels.copy_capture);
+            self.blit.draw(&mut rpass, &source);
+            rpass.pop_debug_group();
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        // lets any readback the app has in flight finish without it having to poll
+        self.device.poll(wgpu::Maintain::Poll);
+
+        let result = match (capture_path, &target, &self.output) {
+            // both are single sampled: with msaa on the scene has already been resolved into them
+            (Some(path), FrameTarget::Capture { texture, .. }, _) | (Some(path), _, Output::Texture { texture, .. }) => {
+                let result = self.save_capture(texture, &path).map_err(RenderError::CaptureError);
+                self.commit_capture(&path);
+                result
+            },
+            _ => Ok(()),
+        };
+        if let (true, FrameTarget::Capture { texture, .. }) = (self.keep_last_frame, target) {
+            self.last_frame = Some(texture);
+        }
+        result
+    }
+
+    // draws the scene (upscaling it if it's scaled down) and then the pre-ui pass into `target`,
+    // or both into the hdr target and tonemaps that into `target`
+    fn encode_scene(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        let scene_target = match (&self.hdr_target, &self.scaled_target) {
+            (Some((texture, _)), _) | (None, Some((texture, _))) => &texture.view,
+            (None, None) => target,
+        };
+
+        {
+            // clears color and depth; scene geometry will get drawn in here.
+            // with msaa on we draw into the multisampled target and resolve into the frame
+            let (attachment, resolve_target) = match &self.msaa_target {
+                Some(msaa) => (&msaa.view, Some(scene_target)),
+                None => (scene_target, None),
+            };
+            let mut rpass = begin_pass(encoder, attachment, resolve_target, wgpu::LoadOp::Clear(self.effective_clear_color()), Some(&self.depth.view));
+            rpass.push_debug_group(&self.labels.scene);
+            rpass.set_bind_group(0, &self.camera_uniform.bind_group, &[]);
+
+            // first, so they show in front of the triangle at the same depth
+            if let Some(particles) = &self.particles {
+                particles.draw(&mut rpass, &self.triangle, self.wireframe);
+            }
+            if self.show_triangle {
+                self.triangle.draw(&mut rpass, self.wireframe);
+            }
+            rpass.pop_debug_group();
+        }
+
+        if let Some((_, source)) = &self.scaled_target {
+            // the blit covers every pixel, so there's nothing to clear
+            let mut rpass = begin_pass(encoder, target, None, wgpu::LoadOp::Load, None);
+            rpass.push_debug_group(&self.labels.upscale);
+            self.blit.draw(&mut rpass, source);
+            rpass.pop_debug_group();
+        }
+
+        // there are no encoder level debug groups here and render passes can't be labeled in this
+        // wgpu, so showing up in captures is up to the pass pushing its own debug group
+        if let Some(pass) = self.pre_ui_pass.borrow_mut().as_mut() {
+            let pass_target = self.hdr_target.as_ref().map_or(target, |(texture, _)| &texture.view);
+            pass(encoder, pass_target, &self.device, &self.queue);
+        }
+
+        if let Some((_, source)) = &self.hdr_target {
+            // covers every pixel too
+            let mut rpass = begin_pass(encoder, target, None, wgpu::LoadOp::Load, None);
+            rpass.push_debug_group(&self.labels.tonemap);
+            self.tonemap.draw(&mut rpass, source);
+            rpass.pop_debug_group();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: f32) -> Duration {
+        Duration::from_secs_f32(ms / 1000.0)
+    }
+
+    fn assert_close(actual: Duration, expected: Duration) {
+        assert!(actual.abs_diff(expected) < Duration::from_micros(1), "{:?} isn't {:?}", actual, expected);
+    }
+
+    #[test]
+    fn smoothing_starts_from_the_first_delta() {
+        for smoothing in &[DeltaSmoothing::None, DeltaSmoothing::ExponentialMovingAverage(0.1), DeltaSmoothing::Clamp(0.1)] {
+            assert_eq!(smoothing.apply(Duration::default(), ms(20.0)), ms(20.0));
+        }
+        assert_eq!(DeltaSmoothing::None.apply(ms(10.0), ms(20.0)), ms(20.0));
+    }
+
+    #[test]
+    fn moving_average_moves_part_of_the_way() {
+        let smoothing = DeltaSmoothing::ExponentialMovingAverage(0.25);
+        assert_close(smoothing.apply(ms(10.0), ms(30.0)), ms(15.0));
+        assert_close(smoothing.apply(ms(30.0), ms(10.0)), ms(25.0));
+        // weights outside 0..1 are clamped
+        assert_close(DeltaSmoothing::ExponentialMovingAverage(2.0).apply(ms(10.0), ms(30.0)), ms(30.0));
+        assert_close(DeltaSmoothing::ExponentialMovingAverage(-1.0).apply(ms(10.0), ms(30.0)), ms(10.0));
+    }
+
+    #[test]
+    fn clamp_limits_the_change_per_frame() {
+        let smoothing = DeltaSmoothing::Clamp(0.1);
+        assert_close(smoothing.apply(ms(10.0), ms(20.0)), ms(11.0));
+        assert_close(smoothing.apply(ms(10.0), ms(5.0)), ms(9.0));
+        assert_close(smoothing.apply(ms(10.0), ms(10.5)), ms(10.5));
+    }
+
+    #[test]
+    fn rects_clamp_to_the_frame() {
+        let size = PhysicalSize::new(100, 50);
+        let rect = |x, y, width, height| Rect { x, y, width, height };
+        assert_eq!(rect(10, 10, 20, 20).clamp_to(size), Some(rect(10, 10, 20, 20)));
+        assert_eq!(rect(90, 40, 20, 20).clamp_to(size), Some(rect(90, 40, 10, 10)));
+        assert_eq!(rect(0, 0, 1000, 1000).clamp_to(size), Some(rect(0, 0, 100, 50)));
+        // entirely outside, or empty to begin with
+        assert_eq!(rect(100, 0, 10, 10).clamp_to(size), None);
+        assert_eq!(rect(200, 200, 10, 10).clamp_to(size), None);
+        assert_eq!(rect(10, 10, 0, 10).clamp_to(size), None);
+    }
+}
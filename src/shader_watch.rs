@@ -0,0 +1,46 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use anyhow::{Context, Result};
+use log::warn;
+use notify::Watcher;
+
+/// Watches shader files on disk so pipelines can be rebuilt when they change.
+pub struct ShaderWatcher {
+    // events stop as soon as this is dropped
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    files: Vec<PathBuf>,
+}
+
+impl ShaderWatcher {
+    pub fn new(files: &[PathBuf]) -> Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).context("couldn't start watching shaders")?;
+
+        // editors tend to save by replacing the file, which a watch on the file itself doesn't survive
+        let mut dirs: Vec<&Path> = files.iter().filter_map(|file| file.parent()).collect();
+        dirs.dedup();
+        for dir in dirs {
+            watcher.watch(dir, notify::RecursiveMode::NonRecursive)
+                .with_context(|| format!("couldn't watch {}", dir.display()))?;
+        }
+
+        Ok(ShaderWatcher { _watcher: watcher, events, files: files.to_owned() })
+    }
+
+    /// Whether any of the files were written to since the last call.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event) if event.kind.is_create() || event.kind.is_modify() => {
+                    // only the shaders' directories are watched, so the name is enough
+                    changed |= event.paths.iter().any(|path| self.files.iter().any(|file| file.file_name() == path.file_name()));
+                },
+                Ok(_) => (),
+                Err(e) => warn!("shader watcher error: {}", e),
+            }
+        }
+        changed
+    }
+}
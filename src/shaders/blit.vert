@@ -0,0 +1,11 @@
+#version 450
+
+layout(location = 0) out vec2 v_uv;
+
+// one triangle big enough to cover the whole target, no vertex buffer needed
+void main() {
+    vec2 corner = vec2(float((gl_VertexIndex << 1) & 2), float(gl_VertexIndex & 2));
+    // ndc is y-up but texture coordinates are y-down
+    v_uv = vec2(corner.x, 1.0 - corner.y);
+    gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+}
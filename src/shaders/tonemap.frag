@@ -0,0 +1,22 @@
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 o_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+layout(set = 0, binding = 2) uniform Tonemap {
+    float u_exposure;
+};
+
+// Narkowicz's fit of the ACES filmic curve, linear in and out
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec4 hdr = texture(sampler2D(t_source, s_source), v_uv);
+    // the srgb target does the encoding on write
+    o_color = vec4(aces(hdr.rgb * u_exposure), 1.0);
+}
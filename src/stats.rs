@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use serde::Serialize;
+
+/// The last few frame times, oldest first, for spotting hitches a plain average hides.
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    times: VecDeque<Duration>,
+    capacity: usize,
+}
+
+impl FrameStats {
+    pub fn new(capacity: usize) -> Self {
+        FrameStats { times: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    pub fn push(&mut self, frame_time: Duration) {
+        if self.times.len() == self.capacity {
+            self.times.pop_front();
+        }
+        self.times.push_back(frame_time);
+    }
+
+    pub fn reset(&mut self) {
+        self.times.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.times.iter().copied()
+    }
+
+    pub fn avg(&self) -> Duration {
+        if self.times.is_empty() {
+            return Duration::default();
+        }
+
+        self.times.iter().sum::<Duration>() / self.times.len() as u32
+    }
+
+    pub fn min(&self) -> Duration {
+        self.iter().min().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> Duration {
+        self.iter().max().unwrap_or_default()
+    }
+
+    /// The frame time `p` percent (0 to 100) of frames were at least as fast as, by nearest rank.
+    pub fn percentile(&self, p: f32) -> Duration {
+        if self.times.is_empty() {
+            return Duration::default();
+        }
+
+        let mut sorted: Vec<_> = self.iter().collect();
+        sorted.sort_unstable();
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * sorted.len() as f32).ceil() as usize;
+        sorted[rank.saturating_sub(1)]
+    }
+
+    pub fn p99(&self) -> Duration {
+        self.percentile(99.0)
+    }
+}
+
+/// What `Renderer::dump_stats_json` writes, for tracking performance across commits. Times are in
+/// milliseconds, over the frames `FrameStats` still has. Fields can be added when
+/// `schema_version` goes up, but never renamed or removed.
+#[derive(Clone, Debug, Serialize)]
+pub struct StatsReport {
+    pub schema_version: u32,
+    pub frames: usize,
+    pub avg_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub p99_ms: f64,
+    /// Of the output, in physical pixels.
+    pub width: u32,
+    pub height: u32,
+    pub adapter: AdapterReport,
+}
+
+/// The parts of `wgpu::AdapterInfo` that tell machines apart; enums are their `Debug` names.
+#[derive(Clone, Debug, Serialize)]
+pub struct AdapterReport {
+    pub name: String,
+    pub backend: String,
+    pub device_type: String,
+    pub vendor: usize,
+    pub device: usize,
+}
+
+impl StatsReport {
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    pub fn new(stats: &FrameStats, width: u32, height: u32, adapter: &wgpu::AdapterInfo) -> Self {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        StatsReport {
+            schema_version: Self::SCHEMA_VERSION,
+            frames: stats.len(),
+            avg_ms: ms(stats.avg()),
+            min_ms: ms(stats.min()),
+            max_ms: ms(stats.max()),
+            p99_ms: ms(stats.p99()),
+            width,
+            height,
+            adapter: AdapterReport {
+                name: adapter.name.clone(),
+                backend: format!("{:?}", adapter.backend),
+                device_type: format!("{:?}", adapter.device_type),
+                vendor: adapter.vendor,
+                device: adapter.device,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_of(millis: &[u64]) -> FrameStats {
+        let mut stats = FrameStats::new(millis.len().max(1));
+        for &ms in millis {
+            stats.push(Duration::from_millis(ms));
+        }
+        stats
+    }
+
+    #[test]
+    fn percentile_is_by_nearest_rank() {
+        let stats = stats_of(&[5, 1, 4, 2, 3, 10, 9, 8, 7, 6]);
+        assert_eq!(stats.percentile(0.0), Duration::from_millis(1));
+        assert_eq!(stats.percentile(10.0), Duration::from_millis(1));
+        assert_eq!(stats.percentile(11.0), Duration::from_millis(2));
+        assert_eq!(stats.percentile(50.0), Duration::from_millis(5));
+        assert_eq!(stats.percentile(99.0), Duration::from_millis(10));
+        assert_eq!(stats.percentile(100.0), Duration::from_millis(10));
+        // out of range clamps rather than panicking
+        assert_eq!(stats.percentile(-5.0), Duration::from_millis(1));
+        assert_eq!(stats.percentile(250.0), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn empty_stats_are_zero() {
+        let stats = FrameStats::new(4);
+        assert!(stats.is_empty());
+        assert_eq!(stats.percentile(50.0), Duration::default());
+        assert_eq!(stats.avg(), Duration::default());
+        assert_eq!(stats.min(), Duration::default());
+        assert_eq!(stats.max(), Duration::default());
+    }
+
+    #[test]
+    fn oldest_frames_drop_out_past_capacity() {
+        let mut stats = stats_of(&[100, 1, 1]);
+        stats.push(Duration::from_millis(4));
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats.max(), Duration::from_millis(4));
+        assert_eq!(stats.avg(), Duration::from_millis(2));
+        stats.reset();
+        assert!(stats.is_empty());
+    }
+}
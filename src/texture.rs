@@ -0,0 +1,83 @@
+use winit::dpi::PhysicalSize;
+
+/// A texture along with the view and sampler it's almost always used through.
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub size: wgpu::Extent3d,
+    pub sample_count: u32,
+}
+
+impl Texture {
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    /// Uploads `image` as an sRGB texture that shaders can sample with linear filtering.
+    pub fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, image: &image::DynamicImage, label: &str) -> Self {
+        Self::from_rgba(device, queue, &image.to_rgba8(), label)
+    }
+
+    /// `from_image` for an image that's already 8 bit rgba, without the copy.
+    pub fn from_rgba(device: &wgpu::Device, queue: &wgpu::Queue, image: &image::RgbaImage, label: &str) -> Self {
+        let (width, height) = image.dimensions();
+        let texture = Self::create(device, label, PhysicalSize::new(width, height), wgpu::TextureFormat::Rgba8UnormSrgb, 1,
+            wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST);
+
+        queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            image,
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: 4 * width,
+                rows_per_image: height,
+            },
+            texture.size,
+        );
+        texture
+    }
+
+    /// A depth buffer that can also be copied out of, for `Renderer::read_depth`. `sample_count`
+    /// has to match the color attachments it's drawn with.
+    pub fn create_depth(device: &wgpu::Device, size: PhysicalSize<u32>, sample_count: u32) -> Self {
+        Self::create(device, "depth texture", size, Self::DEPTH_FORMAT, sample_count,
+            wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC)
+    }
+
+    /// A 2d texture with one mip level, for drawing into and whatever else `usage` says.
+    pub fn create(
+        device: &wgpu::Device,
+        label: &str,
+        size: PhysicalSize<u32>,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        usage: wgpu::TextureUsage,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Texture { texture, view, sampler, size, sample_count }
+    }
+}
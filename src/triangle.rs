@@ -0,0 +1,172 @@
+use std::mem::size_of;
+use std::path::Path;
+use anyhow::{anyhow, Context, Result};
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::renderer::catch_wgpu_panic;
+use crate::texture::Texture;
+
+// also what the particles are made of, so they can be drawn with this pipeline
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct Vertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+// tipped back at the top, so it's not all at one depth (and the depth view has something to show)
+const VERTICES: &[Vertex] = &[
+    Vertex { position: [0.0, 0.5, -0.5], color: [1.0, 0.0, 0.0] },
+    Vertex { position: [-0.5, -0.5, 0.5], color: [0.0, 1.0, 0.0] },
+    Vertex { position: [0.5, -0.5, 0.5], color: [0.0, 0.0, 1.0] },
+];
+
+// this wgpu has no Features::NON_FILL_POLYGON_MODE, so wireframe draws each triangle's edges as
+// lines instead, through an index buffer that turns triangle i into 3i..3i+2 pairs. that covers
+// this many vertices, which is plenty for the triangle and the particles
+const MAX_WIREFRAME_VERTICES: u32 = 3 * 1024;
+
+pub struct TrianglePipeline {
+    pipeline: wgpu::RenderPipeline,
+    wireframe_pipeline: wgpu::RenderPipeline,
+    edge_indices: wgpu::Buffer,
+    vertex_buffer: wgpu::Buffer,
+}
+
+impl TrianglePipeline {
+    /// `camera_layout` is `Renderer::camera_bind_group_layout`, which the pipeline expects at set 0.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32, camera_layout: &wgpu::BindGroupLayout) -> Self {
+        // naga's WGSL frontend in this wgpu can't compile even a shader this simple, so these are
+        // SPIR-V built from the GLSL next to them, e.g.
+        //   naga --input-kind glsl --shader-stage vert --keep-coordinate-space --spirv-version 1.0 triangle.vert triangle.vert.spv
+        let vs_module = device.create_shader_module(wgpu::include_spirv!("shaders/triangle.vert.spv"));
+        let fs_module = device.create_shader_module(wgpu::include_spirv!("shaders/triangle.frag.spv"));
+
+        Self::from_modules(device, format, sample_count, camera_layout, &vs_module, &fs_module)
+    }
+
+    /// Builds the pipeline from SPIR-V files on disk instead of the ones baked into the binary.
+    pub fn load(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        camera_layout: &wgpu::BindGroupLayout,
+        vs_path: &Path,
+        fs_path: &Path,
+    ) -> Result<Self> {
+        let vs = read_spirv(vs_path)?;
+        let fs = read_spirv(fs_path)?;
+
+        catch_wgpu_panic(|| {
+            let vs_module = device.create_shader_module(wgpu::util::make_spirv(&vs));
+            let fs_module = device.create_shader_module(wgpu::util::make_spirv(&fs));
+            Self::from_modules(device, format, sample_count, camera_layout, &vs_module, &fs_module)
+        }).map_err(|reason| anyhow!("couldn't build the triangle pipeline: {}", reason))
+    }
+
+    fn from_modules(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        camera_layout: &wgpu::BindGroupLayout,
+        vs_module: &wgpu::ShaderModule,
+        fs_module: &wgpu::ShaderModule,
+    ) -> Self {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("triangle pipeline layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+
+        let build = |label, primitive_topology| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: vs_module,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: fs_module,
+                entry_point: "main",
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                ..Default::default()
+            }),
+            primitive_topology,
+            color_states: &[wgpu::ColorStateDescriptor {
+                format,
+                color_blend: wgpu::BlendDescriptor::REPLACE,
+                alpha_blend: wgpu::BlendDescriptor::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+            depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilStateDescriptor::default(),
+            }),
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint16,
+                vertex_buffers: &[wgpu::VertexBufferDescriptor {
+                    stride: size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float3, 1 => Float3],
+                }],
+            },
+            sample_count,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        });
+        let pipeline = build("triangle pipeline", wgpu::PrimitiveTopology::TriangleList);
+        let wireframe_pipeline = build("triangle wireframe pipeline", wgpu::PrimitiveTopology::LineList);
+
+        let edges: Vec<u16> = (0..MAX_WIREFRAME_VERTICES as u16).step_by(3)
+            .flat_map(|first| [first, first + 1, first + 1, first + 2, first + 2, first])
+            .collect();
+        let edge_indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("wireframe edge index buffer"),
+            contents: bytemuck::cast_slice(&edges),
+            usage: wgpu::BufferUsage::INDEX,
+        });
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("triangle vertex buffer"),
+            contents: bytemuck::cast_slice(VERTICES),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        TrianglePipeline { pipeline, wireframe_pipeline, edge_indices, vertex_buffer }
+    }
+
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, wireframe: bool) {
+        self.draw_vertices(rpass, &self.vertex_buffer, VERTICES.len() as u32, wireframe);
+    }
+
+    // the first `count` `Vertex`es in `buffer`, as a triangle list or just their outlines
+    pub fn draw_vertices<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, buffer: &'a wgpu::Buffer, count: u32, wireframe: bool) {
+        rpass.set_vertex_buffer(0, buffer.slice(..));
+        if wireframe {
+            rpass.set_pipeline(&self.wireframe_pipeline);
+            rpass.set_index_buffer(self.edge_indices.slice(..));
+            // two indices per edge, three edges per three vertices
+            rpass.draw_indexed(0..count.min(MAX_WIREFRAME_VERTICES) * 2, 0, 0..1);
+        } else {
+            rpass.set_pipeline(&self.pipeline);
+            rpass.draw(0..count, 0..1);
+        }
+    }
+}
+
+// make_spirv asserts on these rather than failing, and a file caught halfway through being written trips them
+fn read_spirv(path: &Path) -> Result<Vec<u8>> {
+    const MAGIC_NUMBER: [u8; 4] = 0x0723_0203u32.to_le_bytes();
+
+    let data = std::fs::read(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    if data.len() % 4 != 0 || !data.starts_with(&MAGIC_NUMBER) {
+        return Err(anyhow!("{} isn't a SPIR-V module", path.display()));
+    }
+    Ok(data)
+}
@@ -0,0 +1,92 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use futures::future::{self, Either};
+use log::error;
+
+/// How long `block_on_timeout` gives a call that's stuck inside the driver to come back after
+/// timing out, before giving up on the process.
+const STUCK_GRACE: Duration = Duration::from_secs(2);
+
+// fires once the watchdog thread says so
+#[derive(Default)]
+struct Alarm {
+    fired: bool,
+    waker: Option<Waker>,
+}
+
+struct AlarmFuture(Arc<Mutex<Alarm>>);
+
+impl Future for AlarmFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let mut alarm = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if alarm.fired {
+            Poll::Ready(())
+        } else {
+            alarm.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// `futures::executor::block_on` that gives up after `timeout`, for adapter and device creation,
+/// which can stall forever on a broken driver. `what` is what's being waited for, for the error.
+///
+/// wgpu does most of its work in the first poll, so a future can also hang without ever
+/// returning `Pending`. There's no getting control back from that, so if it's still stuck a
+/// little after the timeout this logs why and keeps waiting; `block_on_timeout_or` can do
+/// something else about it.
+pub fn block_on_timeout<F: Future>(future: F, timeout: Duration, what: &str) -> Result<F::Output> {
+    block_on_timeout_or(future, timeout, what, |message| error!("{}", message))
+}
+
+/// `block_on_timeout` that calls `on_stuck` with the error message instead of logging it when the
+/// future is stuck inside a call, e.g. to exit the process rather than hang. It runs on the
+/// watchdog's own thread, while this one is still blocked.
+pub fn block_on_timeout_or<F: Future>(
+    future: F,
+    timeout: Duration,
+    what: &str,
+    on_stuck: impl FnOnce(&str) + Send + 'static,
+) -> Result<F::Output> {
+    let alarm = Arc::new(Mutex::new(Alarm::default()));
+    let (done, finished) = mpsc::channel::<()>();
+
+    let watched = alarm.clone();
+    let message = format!("{} didn't finish within {:?}; this usually means a broken or hung gpu driver, \
+        try updating it or another --backend", what, timeout);
+    let watchdog_message = message.clone();
+    let watchdog = thread::spawn(move || {
+        if finished.recv_timeout(timeout) != Err(mpsc::RecvTimeoutError::Timeout) {
+            return;
+        }
+        let waker = {
+            let mut alarm = watched.lock().unwrap_or_else(|e| e.into_inner());
+            alarm.fired = true;
+            alarm.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        // still going means it's blocked inside a call rather than waiting to be polled
+        if finished.recv_timeout(STUCK_GRACE) == Err(mpsc::RecvTimeoutError::Timeout) {
+            on_stuck(&watchdog_message);
+        }
+    });
+
+    let result = futures::executor::block_on(future::select(Box::pin(future), AlarmFuture(alarm)));
+    // the watchdog only waits on this, so it's fine if it's already gone
+    let _ = done.send(());
+    let _ = watchdog.join();
+
+    match result {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => Err(anyhow!(message)),
+    }
+}